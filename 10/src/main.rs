@@ -17,72 +17,410 @@ where
     input.as_ref().trim().parse().unwrap()
 }
 
-enum AnswerType {
-    Syntax(i64),
-    Incomplete(i64),
+/// One kind of chunk: its delimiters and what it scores when it goes wrong.
+struct BracketPair {
+    open: String,
+    close: String,
+    /// Score when `close` turns up where another closer was expected.
+    syntax_score: i64,
+    /// Digit added per `close` in an autocompletion.
+    completion_score: i64,
 }
 
-fn brackets_match(start: char, end: char) -> bool {
-    match start {
-        '(' => end == ')',
-        '[' => end == ']',
-        '{' => end == '}',
-        '<' => end == '>',
-        _ => panic!("invalid bracket {}", start),
+/// Configurable delimiter set and scoring; `standard` gives the puzzle's rules.
+struct BracketSpec {
+    pairs: Vec<BracketPair>,
+    completion_base: i64,
+    /// Skip characters outside any delimiter instead of reporting them.
+    ignore_other: bool,
+}
+
+impl BracketSpec {
+    fn standard() -> BracketSpec {
+        let pairs = [
+            ("(", ")", 3, 1),
+            ("[", "]", 57, 2),
+            ("{", "}", 1197, 3),
+            ("<", ">", 25137, 4),
+        ];
+        BracketSpec {
+            pairs: pairs
+                .iter()
+                .map(
+                    |(open, close, syntax_score, completion_score)| BracketPair {
+                        open: open.to_string(),
+                        close: close.to_string(),
+                        syntax_score: *syntax_score,
+                        completion_score: *completion_score,
+                    },
+                )
+                .collect(),
+            completion_base: 5,
+            ignore_other: false,
+        }
+    }
+
+    /// Reads a spec file with one `open close syntax-score completion-score` line per
+    /// pair, plus optional `base N` and `ignore-other` lines. `#` starts a comment line.
+    fn parse_input(s: &str) -> Result<BracketSpec, String> {
+        let mut spec = BracketSpec {
+            pairs: vec![],
+            completion_base: 5,
+            ignore_other: false,
+        };
+        for l in s.lines().map(|l| l.trim()) {
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            match l.split_whitespace().collect::<Vec<_>>()[..] {
+                ["base", n] => spec.completion_base = n.parse().map_err(|_| l.to_owned())?,
+                ["ignore-other"] => spec.ignore_other = true,
+                [open, close, syntax, completion] => spec.pairs.push(BracketPair {
+                    open: open.to_owned(),
+                    close: close.to_owned(),
+                    syntax_score: syntax.parse().map_err(|_| l.to_owned())?,
+                    completion_score: completion.parse().map_err(|_| l.to_owned())?,
+                }),
+                _ => return Err(format!("invalid spec line '{}'", l)),
+            }
+        }
+        if spec.pairs.is_empty() {
+            return Err("spec has no bracket pairs".to_owned());
+        }
+        Ok(spec)
+    }
+
+    /// Longest delimiter starting at the front of `s`, with its pair and whether it opens.
+    /// On a tie the `expected` closer wins, so a delimiter that is its own closer (like a
+    /// quote) closes its chunk rather than opening another.
+    fn match_token(&self, s: &str, expected: Option<&str>) -> Option<(&BracketPair, bool, usize)> {
+        let mut best: Option<(&BracketPair, bool, usize)> = None;
+        for p in self.pairs.iter() {
+            for (token, opens) in [(&p.open, true), (&p.close, false)] {
+                if !s.starts_with(token.as_str()) {
+                    continue;
+                }
+                let closes_expected = !opens && expected == Some(token.as_str());
+                if best.is_none_or(|b| token.len() > b.2 || (token.len() == b.2 && closes_expected))
+                {
+                    best = Some((p, opens, token.len()));
+                }
+            }
+        }
+        best
+    }
+
+    /// Whether `s` is a strict prefix of some delimiter, so more input is needed to tell.
+    fn needs_more(&self, s: &str) -> bool {
+        self.pairs
+            .iter()
+            .flat_map(|p| [&p.open, &p.close])
+            .any(|t| t.len() > s.len() && t.starts_with(s))
+    }
+
+    fn syntax_score(&self, found: &str) -> i64 {
+        self.pairs
+            .iter()
+            .find(|p| p.close == found)
+            .map_or(0, |p| p.syntax_score)
+    }
+
+    fn auto_complete(&self, completion: &[String]) -> i64 {
+        let mut score = 0;
+        for x in completion.iter() {
+            let val = self
+                .pairs
+                .iter()
+                .find(|p| p.close == *x)
+                .map_or(0, |p| p.completion_score);
+            score = (score * self.completion_base) + val;
+        }
+        score
     }
 }
 
-fn find_error(s: &str) -> AnswerType {
-    let mut stack = Vec::new();
-    for x in s.chars() {
-        if x == '(' || x == '<' || x == '[' || x == '{' {
-            stack.push(x);
-        } else {
-            let c = stack.pop().unwrap_or(' ');
-            if brackets_match(c, x) {
+/// Outcome of checking one line, positions are 1-based (line, column) pairs.
+#[derive(Debug, PartialEq)]
+enum CheckResult {
+    /// A closing delimiter that doesn't match the innermost open chunk. `expected` and
+    /// `opened` (where that chunk started) are None when there was no open chunk at all.
+    Corrupted {
+        line: usize,
+        column: usize,
+        found: String,
+        expected: Option<String>,
+        opened: Option<(usize, usize)>,
+    },
+    /// The line ran out with chunks still open; `completion` lists the closers needed,
+    /// innermost first, and `opened` where each of those chunks started.
+    Incomplete {
+        line: usize,
+        completion: Vec<String>,
+        opened: Vec<(usize, usize)>,
+    },
+    /// Every chunk was closed.
+    Complete { line: usize },
+    /// A character that doesn't start any delimiter.
+    InvalidChar {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+}
+
+/// Incremental checker fed arbitrary pieces of input. Each newline (or the end of input)
+/// finishes a line; a stream without newlines is checked as one long line. Only the open
+/// chunks and any partially received delimiter are held in memory.
+struct StreamChecker<'a> {
+    spec: &'a BracketSpec,
+    /// Expected closer and start position of each open chunk.
+    stack: Vec<(&'a str, (usize, usize))>,
+    /// Input not yet consumed because it may be the start of a longer delimiter.
+    pending: String,
+    /// Position of the first character of `pending`.
+    line: usize,
+    column: usize,
+    /// Line that started this record.
+    record_line: usize,
+    /// Set once the current line has failed; the rest of it is skipped.
+    failed: bool,
+}
+
+impl<'a> StreamChecker<'a> {
+    fn new(spec: &'a BracketSpec, line: usize) -> StreamChecker<'a> {
+        StreamChecker {
+            spec,
+            stack: vec![],
+            pending: String::new(),
+            line,
+            column: 1,
+            record_line: line,
+            failed: false,
+        }
+    }
+
+    /// Consumes a piece of input, calling `out` for every line it completes.
+    fn feed<F: FnMut(CheckResult)>(&mut self, text: &str, out: &mut F) {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.push_str(text);
+        let mut consumed = 0;
+        while consumed < pending.len() {
+            let rest = &pending[consumed..];
+            let x = rest.chars().next().unwrap();
+            if x == '\n' {
+                self.end_line(out);
+                self.line += 1;
+                self.column = 1;
+                consumed += 1;
                 continue;
             }
-            return match x {
-                ')' => AnswerType::Syntax(3),
-                ']' => AnswerType::Syntax(57),
-                '}' => AnswerType::Syntax(1197),
-                '>' => AnswerType::Syntax(25137),
-                _ => panic!("invalid bracket {}", x),
-            };
+            if self.failed || x == '\r' {
+                self.column += 1;
+                consumed += x.len_utf8();
+                continue;
+            }
+            if self.spec.needs_more(rest) {
+                break;
+            }
+            let len = self.step(rest, out);
+            self.column += rest[..len].chars().count();
+            consumed += len;
+        }
+        pending.drain(..consumed);
+        self.pending = pending;
+    }
+
+    /// Flushes anything held back and finishes the last line if it had any content.
+    fn finish<F: FnMut(CheckResult)>(&mut self, out: &mut F) {
+        let rest = std::mem::take(&mut self.pending);
+        let mut rest = rest.as_str();
+        while !self.failed && !rest.is_empty() {
+            let len = self.step(rest, out);
+            self.column += rest[..len].chars().count();
+            rest = &rest[len..];
+        }
+        if self.column > 1 {
+            self.end_line(out);
+        }
+    }
+
+    /// Handles the token at the front of `rest`, returning how many bytes it used.
+    fn step<F: FnMut(CheckResult)>(&mut self, rest: &str, out: &mut F) -> usize {
+        let pos = (self.line, self.column);
+        match self.spec.match_token(rest, self.stack.last().map(|e| e.0)) {
+            Some((pair, true, len)) => {
+                self.stack.push((&pair.close, pos));
+                len
+            }
+            Some((pair, false, len)) => {
+                let expected = self.stack.pop();
+                if expected.map(|e| e.0) != Some(pair.close.as_str()) {
+                    self.failed = true;
+                    out(CheckResult::Corrupted {
+                        line: pos.0,
+                        column: pos.1,
+                        found: pair.close.clone(),
+                        expected: expected.map(|e| e.0.to_owned()),
+                        opened: expected.map(|e| e.1),
+                    });
+                }
+                len
+            }
+            None => {
+                let x = rest.chars().next().unwrap();
+                if !self.spec.ignore_other {
+                    self.failed = true;
+                    out(CheckResult::InvalidChar {
+                        line: pos.0,
+                        column: pos.1,
+                        found: x,
+                    });
+                }
+                x.len_utf8()
+            }
         }
     }
-    AnswerType::Incomplete(auto_complete(&stack))
+
+    fn end_line<F: FnMut(CheckResult)>(&mut self, out: &mut F) {
+        if !self.failed {
+            out(if self.stack.is_empty() {
+                CheckResult::Complete {
+                    line: self.record_line,
+                }
+            } else {
+                CheckResult::Incomplete {
+                    line: self.record_line,
+                    completion: self.stack.iter().rev().map(|c| c.0.to_owned()).collect(),
+                    opened: self.stack.iter().rev().map(|c| c.1).collect(),
+                }
+            });
+        }
+        self.stack.clear();
+        self.failed = false;
+        self.record_line = self.line + 1;
+    }
 }
 
-fn auto_complete(s: &Vec<char>) -> i64 {
-    let mut score = 0;
-    for x in s.iter().rev() {
-        let val = match x {
-            '(' => 1,
-            '[' => 2,
-            '{' => 3,
-            '<' => 4,
-            _ => panic!("invalid bracket {}", x),
-        };
-        score = (score * 5) + val;
+/// Running median of a stream of values, with the lower half in a max-heap and the upper
+/// half in a min-heap. Like indexing a sorted list at len / 2, the upper middle value is
+/// the median of an even count.
+struct RunningMedian {
+    lower: BinaryHeap<i64>,
+    upper: BinaryHeap<std::cmp::Reverse<i64>>,
+}
+
+impl RunningMedian {
+    fn new() -> RunningMedian {
+        RunningMedian {
+            lower: BinaryHeap::new(),
+            upper: BinaryHeap::new(),
+        }
+    }
+
+    fn push(&mut self, v: i64) {
+        if self.lower.peek().is_some_and(|l| v < *l) {
+            self.lower.push(v);
+        } else {
+            self.upper.push(std::cmp::Reverse(v));
+        }
+        // keep upper the same size as lower or one larger
+        if self.lower.len() > self.upper.len() {
+            self.upper
+                .push(std::cmp::Reverse(self.lower.pop().unwrap()));
+        } else if self.upper.len() > self.lower.len() + 1 {
+            self.lower.push(self.upper.pop().unwrap().0);
+        }
+    }
+
+    fn median(&self) -> Option<i64> {
+        self.upper.peek().map(|v| v.0)
+    }
+}
+
+fn report(result: &CheckResult) {
+    match result {
+        CheckResult::Corrupted {
+            line,
+            column,
+            found,
+            expected: Some(e),
+            opened: Some(o),
+        } => println!(
+            "{}:{}: expected {} (chunk opened at {}:{}), found {}",
+            line, column, e, o.0, o.1, found
+        ),
+        CheckResult::Corrupted {
+            line,
+            column,
+            found,
+            ..
+        } => println!("{}:{}: unexpected {}", line, column, found),
+        CheckResult::Incomplete {
+            line, completion, ..
+        } => println!(
+            "{}: incomplete, complete with {}",
+            line,
+            completion.concat()
+        ),
+        _ => {}
     }
-    score
 }
 
 fn main() {
-    let stdin = io::stdin();
-    let values: Vec<String> = stdin.lock().lines().map(|input| input.unwrap()).collect();
+    let args = std::env::args().collect::<Vec<_>>();
+    let verbose = args.iter().any(|a| a == "--report");
+    let spec = match args.iter().position(|a| a == "--spec") {
+        Some(i) => {
+            let path = args.get(i + 1).expect("--spec needs a file");
+            BracketSpec::parse_input(&std::fs::read_to_string(path).unwrap()).unwrap()
+        }
+        None => BracketSpec::standard(),
+    };
 
     let mut errors = 0;
-    let mut completions = Vec::new();
-    for s in values.iter() {
-        match find_error(s) {
-            AnswerType::Incomplete(p) => completions.push(p),
-            AnswerType::Syntax(p) => errors += p,
+    let mut completions = RunningMedian::new();
+    let mut handle = |result: CheckResult| {
+        match &result {
+            CheckResult::Incomplete { completion, .. } => {
+                completions.push(spec.auto_complete(completion))
+            }
+            CheckResult::Corrupted { found, .. } => errors += spec.syntax_score(found),
+            CheckResult::InvalidChar {
+                line,
+                column,
+                found,
+            } => eprintln!("{}:{}: invalid character {:?}", line, column, found),
+            CheckResult::Complete { .. } => {}
+        }
+        if verbose {
+            report(&result);
         }
+    };
+
+    // read in fixed size blocks so neither long lines nor long files are held in memory
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut checker = StreamChecker::new(&spec, 1);
+    let mut buf = vec![0u8; 1 << 16];
+    let mut carry = 0;
+    loop {
+        let n = input.read(&mut buf[carry..]).unwrap();
+        if n == 0 {
+            break;
+        }
+        let end = carry + n;
+        // a multi-byte character may be split across reads
+        let valid = match std::str::from_utf8(&buf[..end]) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => panic!("invalid utf-8 in input: {}", e),
+        };
+        checker.feed(std::str::from_utf8(&buf[..valid]).unwrap(), &mut handle);
+        buf.copy_within(valid..end, 0);
+        carry = end - valid;
     }
+    checker.finish(&mut handle);
 
     println!("{:?}", errors);
-    completions.sort();
-    println!("{:?}", completions[completions.len() / 2]);
+    println!("{:?}", completions.median().unwrap_or(0));
 }
@@ -28,38 +28,203 @@ const DIRECTIONS: [(i32, i32); 8] = [
     (-1, 1),
 ];
 
-fn update_grid(grid: &mut HashMap<(i32, i32), i32>) -> i32 {
+const VON_NEUMANN: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+enum Neighbourhood {
+    /// The 4 orthogonal neighbours.
+    VonNeumann,
+    /// All 8 surrounding cells.
+    Moore,
+    Custom(Vec<(i32, i32)>),
+}
+
+impl Neighbourhood {
+    fn offsets(&self) -> &[(i32, i32)] {
+        match self {
+            Neighbourhood::VonNeumann => &VON_NEUMANN,
+            Neighbourhood::Moore => &DIRECTIONS,
+            Neighbourhood::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// How energy spreads through the grid; `Rules::new` gives the puzzle's rules.
+struct Rules {
+    /// An octopus flashes once its energy is above this.
+    threshold: i32,
+    /// Energy of an octopus after it has flashed.
+    reset: i32,
+    /// Energy each neighbour gains from a flash.
+    gain: i32,
+    neighbourhood: Neighbourhood,
+    /// Neighbours off one edge wrap around to the opposite edge.
+    wrap: bool,
+}
+
+impl Rules {
+    fn new() -> Rules {
+        Rules {
+            threshold: 9,
+            reset: 0,
+            gain: 1,
+            neighbourhood: Neighbourhood::Moore,
+            wrap: false,
+        }
+    }
+
+    /// Energies stay within a fixed range, and so a run eventually repeats, only when
+    /// flashes can't drain energy and a flashed octopus starts back at or below the threshold.
+    fn validate(&self) -> Result<(), String> {
+        if self.gain < 0 {
+            return Err(format!("gain {} is negative", self.gain));
+        }
+        if self.reset > self.threshold {
+            return Err(format!(
+                "reset {} is above the threshold {}",
+                self.reset, self.threshold
+            ));
+        }
+        Ok(())
+    }
+
+    /// Neighbour of p in direction d, or None if it falls off a non-wrapping edge.
+    fn neighbour(&self, p: (i32, i32), d: (i32, i32), size: (i32, i32)) -> Option<(i32, i32)> {
+        let np = (p.0 + d.0, p.1 + d.1);
+        if self.wrap {
+            Some((np.0.rem_euclid(size.0), np.1.rem_euclid(size.1)))
+        } else if np.0 >= 0 && np.1 >= 0 && np.0 < size.0 && np.1 < size.1 {
+            Some(np)
+        } else {
+            None
+        }
+    }
+}
+
+fn update_grid(grid: &mut HashMap<(i32, i32), i32>, rules: &Rules) -> i32 {
+    let size = (
+        grid.keys().map(|p| p.0).max().map_or(0, |x| x + 1),
+        grid.keys().map(|p| p.1).max().map_or(0, |y| y + 1),
+    );
     let mut queue = VecDeque::new();
     for (k, v) in grid.iter_mut() {
         *v += 1;
-        if *v > 9 {
-            queue.push_back(k.clone());
+        if *v > rules.threshold {
+            queue.push_back(*k);
         }
     }
     let mut flashed = HashSet::new();
-    while queue.is_empty() == false {
+    while !queue.is_empty() {
         let p = queue.pop_front().unwrap();
-        if flashed.insert(p) == false {
+        if !flashed.insert(p) {
             continue;
         }
-        for x in DIRECTIONS.iter() {
-            let np = (p.0 + x.0, p.1 + x.1);
-            if flashed.contains(&np) || (grid.contains_key(&np) == false) {
+        for d in rules.neighbourhood.offsets().iter() {
+            let np = match rules.neighbour(p, *d, size) {
+                Some(np) => np,
+                None => continue,
+            };
+            if flashed.contains(&np) || !grid.contains_key(&np) {
                 continue;
             }
             let cc = grid.entry(np).or_default();
-            *cc += 1;
-            if *cc > 9 {
+            *cc += rules.gain;
+            if *cc > rules.threshold {
                 queue.push_back(np);
             }
         }
     }
     for x in flashed.iter() {
-        *grid.entry(*x).or_default() = 0;
+        *grid.entry(*x).or_default() = rules.reset;
     }
     flashed.len() as i32
 }
 
+/// Steps a grid until it revisits an earlier state, after which every later step is known.
+/// `states[n]` is the grid after n steps (states[0] is the input) and `flashes[n]` the
+/// total flashes over those n steps. `flashes` also covers the step that closed the cycle.
+struct Simulation {
+    keys: Vec<(i32, i32)>,
+    states: Vec<Vec<i32>>,
+    flashes: Vec<u64>,
+    /// First step of the repeating section and its length.
+    cycle: (usize, usize),
+}
+
+impl Simulation {
+    /// Fails on invalid rules, or if no state repeats within `max_steps` steps.
+    fn run(
+        mut grid: HashMap<(i32, i32), i32>,
+        rules: &Rules,
+        max_steps: usize,
+    ) -> Result<Simulation, String> {
+        rules.validate()?;
+        let mut keys = grid.keys().copied().collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|p| (p.1, p.0));
+        let snapshot = |grid: &HashMap<(i32, i32), i32>| -> Vec<i32> {
+            keys.iter().map(|p| grid[p]).collect()
+        };
+
+        let mut seen = HashMap::new();
+        let mut states = vec![snapshot(&grid)];
+        let mut flashes = vec![0];
+        seen.insert(states[0].clone(), 0);
+        let cycle = loop {
+            let total = flashes.last().unwrap() + update_grid(&mut grid, rules) as u64;
+            flashes.push(total);
+            let state = snapshot(&grid);
+            let step = states.len();
+            if step > max_steps {
+                return Err(format!("no repeated state within {} steps", max_steps));
+            }
+            if let Some(first) = seen.insert(state.clone(), step) {
+                break (first, step - first);
+            }
+            states.push(state);
+        };
+        Ok(Simulation {
+            keys,
+            states,
+            flashes,
+            cycle,
+        })
+    }
+
+    /// Where step n lands in the recorded history, and how many whole cycles it skipped.
+    fn position(&self, n: u64) -> (usize, u64) {
+        let (start, len) = self.cycle;
+        if n < self.states.len() as u64 {
+            return (n as usize, 0);
+        }
+        let past = n - start as u64;
+        (start + (past % len as u64) as usize, past / len as u64)
+    }
+
+    fn state_at(&self, n: u64) -> HashMap<(i32, i32), i32> {
+        let state = &self.states[self.position(n).0];
+        self.keys
+            .iter()
+            .copied()
+            .zip(state.iter().copied())
+            .collect()
+    }
+
+    fn flashes_after(&self, n: u64) -> u64 {
+        if n < self.flashes.len() as u64 {
+            return self.flashes[n as usize];
+        }
+        let (start, len) = self.cycle;
+        let per_cycle = self.flashes[start + len] - self.flashes[start];
+        let (idx, cycles) = self.position(n);
+        self.flashes[idx] + cycles * per_cycle
+    }
+
+    /// First step on which every octopus flashes, if that ever happens.
+    fn first_synchronised(&self) -> Option<usize> {
+        (1..self.flashes.len())
+            .find(|&n| self.flashes[n] - self.flashes[n - 1] == self.keys.len() as u64)
+    }
+}
+
 fn main() {
     let stdin = io::stdin();
     let values: Vec<String> = stdin.lock().lines().map(|input| input.unwrap()).collect();
@@ -71,20 +236,70 @@ fn main() {
             grid.insert(p, c.to_digit(10).unwrap() as i32);
         }
     }
-    let mut flashes = 0;
-    let mut p1ans = 0;
-    let mut p2ans = 0;
-    for step in 1.. {
-        let nflashes = update_grid(&mut grid);
-        flashes += nflashes;
-        if step == 100 {
-            p1ans = flashes;
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("missing argument value"))
+    };
+    let mut rules = Rules::new();
+    if let Some(v) = arg("--threshold") {
+        rules.threshold = parse_input(v);
+    }
+    if let Some(v) = arg("--reset") {
+        rules.reset = parse_input(v);
+    }
+    if let Some(v) = arg("--gain") {
+        rules.gain = parse_input(v);
+    }
+    if let Some(v) = arg("--neighbourhood") {
+        rules.neighbourhood = match v.as_str() {
+            "von-neumann" => Neighbourhood::VonNeumann,
+            "moore" => Neighbourhood::Moore,
+            // dx,dy;dx,dy;...
+            offsets => Neighbourhood::Custom(
+                offsets
+                    .split(';')
+                    .map(|o| {
+                        let d = o.split(',').map(parse_input).collect::<Vec<i32>>();
+                        (d[0], d[1])
+                    })
+                    .collect(),
+            ),
+        };
+    }
+    rules.wrap = args.iter().any(|a| a == "--wrap");
+
+    let max_steps = arg("--max-steps").map_or(100_000, parse_input);
+
+    let sim = match Simulation::run(grid, &rules, max_steps) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
-        if nflashes == grid.len() as i32 {
-            p2ans = step;
-            break;
+    };
+    println!("{:?}", sim.flashes_after(100));
+    match sim.first_synchronised() {
+        Some(n) => println!("{:?}", n),
+        None => println!("never synchronised"),
+    }
+
+    if let Some(v) = arg("--steps") {
+        let n: u64 = parse_input(v);
+        println!(
+            "cycle starts at step {} with length {}",
+            sim.cycle.0, sim.cycle.1
+        );
+        println!("flashes after {} steps: {}", n, sim.flashes_after(n));
+        let state = sim.state_at(n);
+        let width = sim.keys.iter().map(|p| p.0).max().unwrap_or(0);
+        let height = sim.keys.iter().map(|p| p.1).max().unwrap_or(0);
+        for y in 0..=height {
+            let row = (0..=width)
+                .map(|x| state.get(&(x, y)).map_or(" ".to_owned(), |v| v.to_string()))
+                .collect::<String>();
+            println!("{}", row);
         }
     }
-    println!("{:?}", p1ans);
-    println!("{:?}", p2ans);
 }
@@ -8,6 +8,8 @@ use std::io::prelude::*;
 use std::fmt::Debug;
 use std::str::FromStr;
 
+use aoc_bignum::BigUint;
+
 fn parse_input<Input, Type>(input: Input) -> Type
 where
     Type: FromStr,
@@ -18,43 +20,273 @@ where
 }
 
 fn is_big_cave(s: &str) -> bool {
-    s.chars().all(|x| x.is_ascii_lowercase()) == false
+    !s.chars().all(|x| x.is_ascii_lowercase())
 }
 
-#[derive(Debug, Clone)]
-struct Route {
-    r: String,
-    visited: HashSet<String>,
-    current: String,
-    second: bool,
+/// Cave system with names interned to ids. Small caves each get a bit so a set of visited
+/// caves fits in a u64.
+struct CaveGraph {
+    names: Vec<String>,
+    big: Vec<bool>,
+    adj: Vec<Vec<usize>>,
+    /// Bit of each small cave in a visited mask.
+    bit: Vec<u64>,
+    start: usize,
+    end: usize,
 }
 
-impl Route {
-    fn visit(&self, x: &str) -> Option<Route> {
-        let mut second = self.second;
-        if is_big_cave(x) == false {
-            if self.visited.contains(x) {
-                if second == false && (x != "start") {
-                    second = true;
-                } else {
-                    return None;
+impl CaveGraph {
+    fn parse_input(edges: &[Vec<String>]) -> Result<CaveGraph, String> {
+        let mut ids = HashMap::new();
+        let mut g = CaveGraph {
+            names: vec![],
+            big: vec![],
+            adj: vec![],
+            bit: vec![],
+            start: 0,
+            end: 0,
+        };
+        let mut small = 0;
+        let mut intern = |g: &mut CaveGraph, name: &str| -> Result<usize, String> {
+            if let Some(id) = ids.get(name) {
+                return Ok(*id);
+            }
+            let big = is_big_cave(name);
+            if !big {
+                if small == 64 {
+                    return Err("more than 64 small caves".to_owned());
                 }
+                small += 1;
+            }
+            g.names.push(name.to_owned());
+            g.big.push(big);
+            g.adj.push(vec![]);
+            g.bit.push(if big { 0 } else { 1 << (small - 1) });
+            ids.insert(name.to_owned(), g.names.len() - 1);
+            Ok(g.names.len() - 1)
+        };
+        for x in edges.iter() {
+            let a = intern(&mut g, &x[0])?;
+            let b = intern(&mut g, &x[1])?;
+            if g.big[a] && g.big[b] {
+                return Err(format!(
+                    "big caves {} and {} are connected, so there are infinitely many paths",
+                    x[0], x[1]
+                ));
+            }
+            if !g.adj[a].contains(&b) {
+                g.adj[a].push(b);
+                g.adj[b].push(a);
             }
         }
-        let mut nr = self.clone();
-        nr.visited.insert(x.to_owned());
-        nr.r += x;
-        nr.current = x.to_owned();
-        nr.second = second;
-        Some(nr)
+        g.start = *ids.get("start").ok_or("no start cave")?;
+        g.end = *ids.get("end").ok_or("no end cave")?;
+        Ok(g)
     }
 
-    fn new() -> Route {
-        Route {
-            r: String::new(),
-            visited: HashSet::new(),
-            current: String::new(),
-            second: false,
+    /// Number of paths from start to end allowed by the given revisit policy.
+    fn count_paths(&self, policy: &RevisitPolicy) -> BigUint {
+        let mut memo = HashMap::new();
+        let state = VisitState {
+            visited: self.bit[self.start],
+            repeats: vec![],
+        };
+        self.count_from(self.start, state, policy, &mut memo)
+    }
+
+    /// State after entering small cave n, or None if the policy doesn't allow it.
+    fn visit(&self, n: usize, state: &VisitState, policy: &RevisitPolicy) -> Option<VisitState> {
+        let mut next = state.clone();
+        if state.visited & self.bit[n] == 0 {
+            next.visited |= self.bit[n];
+            return Some(next);
+        }
+        let can_add = next.repeats.len() < policy.caves as usize && policy.max_visits >= 2;
+        match next.repeats.iter_mut().find(|r| r.0 == n) {
+            Some(r) if r.1 < policy.max_visits => r.1 += 1,
+            Some(_) => return None,
+            None if can_add => {
+                next.repeats.push((n, 2));
+                next.repeats.sort_unstable();
+            }
+            None => return None,
+        }
+        Some(next)
+    }
+
+    fn count_from(
+        &self,
+        node: usize,
+        state: VisitState,
+        policy: &RevisitPolicy,
+        memo: &mut HashMap<(usize, VisitState), BigUint>,
+    ) -> BigUint {
+        if let Some(c) = memo.get(&(node, state.clone())) {
+            return c.clone();
+        }
+        let mut total = BigUint::zero();
+        for &n in self.adj[node].iter() {
+            if n == self.start && !policy.revisit_start {
+                continue;
+            }
+            let next = if self.big[n] {
+                state.clone()
+            } else {
+                match self.visit(n, &state, policy) {
+                    Some(next) => next,
+                    None => continue,
+                }
+            };
+            if n == self.end {
+                total.add_assign(&BigUint::one());
+                if !policy.pass_through_end {
+                    continue;
+                }
+            }
+            total.add_assign(&self.count_from(n, next, policy, memo));
+        }
+        memo.insert((node, state), total.clone());
+        total
+    }
+
+    /// Graphviz description of the cave system, big caves as filled boxes.
+    fn to_dot(&self) -> String {
+        let mut out = String::from("graph caves {\n");
+        for (id, name) in self.names.iter().enumerate() {
+            let style = if self.big[id] {
+                "shape=box, style=filled, fillcolor=lightblue"
+            } else if id == self.start || id == self.end {
+                "shape=doublecircle"
+            } else {
+                "shape=circle"
+            };
+            out += &format!("    \"{}\" [{}];\n", name, style);
+        }
+        for (a, dests) in self.adj.iter().enumerate() {
+            for &b in dests.iter().filter(|&&b| a < b) {
+                out += &format!("    \"{}\" -- \"{}\";\n", self.names[a], self.names[b]);
+            }
+        }
+        out += "}\n";
+        out
+    }
+
+    /// Lazily lists every path allowed by the policy, as cave ids from start to end.
+    fn paths<'a>(&'a self, policy: &'a RevisitPolicy) -> Paths<'a> {
+        let state = VisitState {
+            visited: self.bit[self.start],
+            repeats: vec![],
+        };
+        Paths {
+            graph: self,
+            policy,
+            stack: vec![(self.start, state, 0)],
+            path: vec![self.start],
+            max_len: None,
+        }
+    }
+
+    fn format_path(&self, path: &[usize], separator: &str) -> String {
+        path.iter()
+            .map(|&id| self.names[id].as_str())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+/// Caves visited so far: a bit per small cave, plus the caves that have been visited more
+/// than once with their visit counts, sorted by cave.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct VisitState {
+    visited: u64,
+    repeats: Vec<(usize, u32)>,
+}
+
+/// Which small caves a path may visit more than once.
+struct RevisitPolicy {
+    /// How many different small caves may be revisited.
+    caves: u32,
+    /// Most visits each of those caves may get.
+    max_visits: u32,
+    /// Start can be re-entered, counting as a small cave.
+    revisit_start: bool,
+    /// Paths may carry on through end instead of stopping the first time they reach it.
+    pass_through_end: bool,
+}
+
+/// Depth first walk over the paths of a cave graph, see `CaveGraph::paths`.
+struct Paths<'a> {
+    graph: &'a CaveGraph,
+    policy: &'a RevisitPolicy,
+    /// Cave, visits so far and next neighbour to try, for each cave on the current path.
+    stack: Vec<(usize, VisitState, usize)>,
+    path: Vec<usize>,
+    /// Paths with more caves than this are never explored.
+    max_len: Option<usize>,
+}
+
+impl Paths<'_> {
+    fn max_len(mut self, len: usize) -> Self {
+        self.max_len = Some(len);
+        self
+    }
+}
+
+impl Iterator for Paths<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let g = self.graph;
+        while let Some((node, state, idx)) = self.stack.last_mut() {
+            let node = *node;
+            let full = match self.max_len {
+                Some(m) => self.path.len() >= m,
+                None => false,
+            };
+            if *idx == g.adj[node].len() || full {
+                self.stack.pop();
+                self.path.pop();
+                continue;
+            }
+            let n = g.adj[node][*idx];
+            *idx += 1;
+            if n == g.start && !self.policy.revisit_start {
+                continue;
+            }
+            let next = if g.big[n] {
+                state.clone()
+            } else {
+                match g.visit(n, state, self.policy) {
+                    Some(next) => next,
+                    None => continue,
+                }
+            };
+            self.path.push(n);
+            let found = if n == g.end {
+                Some(self.path.clone())
+            } else {
+                None
+            };
+            if n != g.end || self.policy.pass_through_end {
+                self.stack.push((n, next, 0));
+            } else {
+                self.path.pop();
+            }
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+impl RevisitPolicy {
+    fn new(caves: u32, max_visits: u32) -> RevisitPolicy {
+        RevisitPolicy {
+            caves,
+            max_visits,
+            revisit_start: false,
+            pass_through_end: false,
         }
     }
 }
@@ -64,36 +296,48 @@ fn main() {
     let values: Vec<Vec<String>> = stdin
         .lock()
         .lines()
-        .map(|input| input.unwrap().split("-").map(|x| parse_input(x)).collect())
+        .map(|input| input.unwrap().split('-').map(parse_input).collect())
         .collect();
-    let mut routes = HashMap::new();
-    for x in values.iter() {
-        {
-            let cc = routes.entry(x[0].clone()).or_insert(HashSet::new());
-            cc.insert(x[1].clone());
-        }
-        {
-            let cc = routes.entry(x[1].clone()).or_insert(HashSet::new());
-            cc.insert(x[0].clone());
+    let graph = CaveGraph::parse_input(&values).unwrap();
+    println!("{}", graph.count_paths(&RevisitPolicy::new(0, 1)));
+    println!("{}", graph.count_paths(&RevisitPolicy::new(1, 2)));
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("missing argument value"))
+    };
+    // k,m: k small caves may each be visited up to m times
+    let mut policy = match arg("--revisit") {
+        Some(km) => {
+            let km = km.split(',').map(parse_input).collect::<Vec<u32>>();
+            RevisitPolicy::new(km[0], km[1])
         }
+        None => RevisitPolicy::new(1, 2),
+    };
+    policy.revisit_start = args.iter().any(|a| a == "--revisit-start");
+    policy.pass_through_end = args.iter().any(|a| a == "--through-end");
+    if arg("--revisit").is_some() {
+        println!("{}", graph.count_paths(&policy));
     }
 
-    let mut unique_routes = HashSet::new();
-    let start = Route::new();
-    let mut queue = VecDeque::new();
-    queue.push_back(start.visit("start").unwrap());
-    while queue.is_empty() == false {
-        let v = queue.pop_front().unwrap();
-        for dests in routes[&v.current].iter() {
-            if dests == "end" {
-                unique_routes.insert(v.r.to_owned());
-            } else {
-                match v.visit(dests) {
-                    Some(p) => queue.push_back(p),
-                    None => {}
-                }
-            }
+    if let Some(path) = arg("--dot") {
+        std::fs::write(path, graph.to_dot()).unwrap();
+    }
+    if args.iter().any(|a| a == "--list") {
+        let separator = arg("--sep").map_or(",", |s| s.as_str());
+        let through = arg("--via").map_or(vec![], |v| v.split(',').collect::<Vec<_>>());
+        let mut paths = graph.paths(&policy);
+        if let Some(len) = arg("--max-len") {
+            paths = paths.max_len(parse_input(len));
+        }
+        for p in paths.filter(|p| {
+            through
+                .iter()
+                .all(|c| p.iter().any(|&id| graph.names[id] == *c))
+        }) {
+            println!("{}", graph.format_path(&p, separator));
         }
     }
-    println!("{:?}", unique_routes.len());
 }
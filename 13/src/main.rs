@@ -17,23 +17,177 @@ where
     input.as_ref().trim().parse().unwrap()
 }
 
-fn calc_new_pos(old_pos: i32, fold_pos: i32) -> i32 {
-    if old_pos < fold_pos {
-        return old_pos;
+/// A fold along the line x = pos (horiz) or y = pos, bringing the far side over. The line
+/// can be anywhere on the paper, not just the middle, but only axis-aligned folds are
+/// supported; diagonal or other slanted lines can't be represented.
+#[derive(Clone, Copy, Debug)]
+struct Fold {
+    pos: i32,
+    horiz: bool,
+}
+
+impl Fold {
+    fn parse_input(s: &str) -> Fold {
+        let c: Vec<&str> = s.split('=').collect();
+        Fold {
+            pos: parse_input(c[1]),
+            horiz: c[0].ends_with('x'),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum FoldError {
+    /// Dots can't sit on the crease itself.
+    DotOnFold((i32, i32)),
+    /// The fold line is at a negative position.
+    OutOfRange(Fold),
+}
+
+/// New position along the folded axis. Whichever side of the fold is longer sets the new
+/// size, and positions are shifted so the folded paper still starts at 0.
+fn calc_new_pos(old_pos: i32, fold_pos: i32, size: i32) -> i32 {
+    let shift = (size - 1 - 2 * fold_pos).max(0);
+    fold_pos - (old_pos - fold_pos).abs() + shift
+}
+
+fn folded_size(fold_pos: i32, size: i32) -> i32 {
+    fold_pos.max(size - 1 - fold_pos)
+}
+
+/// Errors for a fold that can't be made with dots at these positions.
+fn check_fold<I: Iterator<Item = (i32, i32)>>(f: Fold, dots: I) -> Result<(), FoldError> {
+    // the puzzle's paper can extend past the last dot, so only a negative fold is an error
+    if f.pos < 0 {
+        return Err(FoldError::OutOfRange(f));
+    }
+    let mut on_fold = dots
+        .filter(|p| if f.horiz { p.0 == f.pos } else { p.1 == f.pos })
+        .collect::<Vec<_>>();
+    on_fold.sort_unstable_by_key(|p| (p.1, p.0));
+    match on_fold.first() {
+        Some(p) => Err(FoldError::DotOnFold(*p)),
+        None => Ok(()),
+    }
+}
+
+/// Sheet of transparent paper that remembers which of the original dots ended up where.
+struct Paper {
+    width: i32,
+    height: i32,
+    /// Original dots that have been folded onto each current dot.
+    origins: HashMap<(i32, i32), Vec<(i32, i32)>>,
+}
+
+impl Paper {
+    fn new(dots: &[(i32, i32)]) -> Paper {
+        Paper {
+            width: dots.iter().map(|p| p.0 + 1).max().unwrap_or(0),
+            height: dots.iter().map(|p| p.1 + 1).max().unwrap_or(0),
+            origins: dots.iter().map(|p| (*p, vec![*p])).collect(),
+        }
+    }
+
+    fn dots(&self) -> HashSet<(i32, i32)> {
+        self.origins.keys().copied().collect()
+    }
+
+    /// Leaves the paper unchanged on an error.
+    fn fold(&mut self, f: Fold) -> Result<(), FoldError> {
+        check_fold(f, self.origins.keys().copied())?;
+        let size = if f.horiz { self.width } else { self.height }.max(f.pos + 1);
+        let mut origins: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+        for (p, from) in self.origins.drain() {
+            let np = if f.horiz {
+                (calc_new_pos(p.0, f.pos, size), p.1)
+            } else {
+                (p.0, calc_new_pos(p.1, f.pos, size))
+            };
+            origins.entry(np).or_default().extend(from);
+        }
+        self.origins = origins;
+        if f.horiz {
+            self.width = folded_size(f.pos, size);
+        } else {
+            self.height = folded_size(f.pos, size);
+        }
+        Ok(())
+    }
+
+    /// Original dots that were folded onto p.
+    fn provenance(&self, p: (i32, i32)) -> &[(i32, i32)] {
+        self.origins.get(&p).map_or(&[], |v| v.as_slice())
+    }
+
+    fn to_bitmap(&self) -> Bitmap {
+        let mut b = Bitmap {
+            width: self.width,
+            height: self.height,
+            cells: vec![false; (self.width * self.height) as usize],
+        };
+        for p in self.origins.keys() {
+            b.cells[(p.1 * self.width + p.0) as usize] = true;
+        }
+        b
     }
-    fold_pos - (old_pos - fold_pos)
 }
 
-fn fold(current_paper: &HashSet<(i32, i32)>, pos: i32, horiz: bool) -> HashSet<(i32, i32)> {
-    let mut new_paper = HashSet::new();
-    for p in current_paper.iter() {
-        if horiz {
-            new_paper.insert((calc_new_pos(p.0, pos), p.1));
+/// Dense row-major version of the paper, for folding large numbers of dots.
+struct Bitmap {
+    width: i32,
+    height: i32,
+    cells: Vec<bool>,
+}
+
+impl Bitmap {
+    /// Fails in the same cases as `Paper::fold`.
+    fn fold(&self, f: Fold) -> Result<Bitmap, FoldError> {
+        let crease: Vec<(i32, i32)> = if f.horiz {
+            (0..self.height).map(|y| (f.pos, y)).collect()
+        } else {
+            (0..self.width).map(|x| (x, f.pos)).collect()
+        };
+        check_fold(f, crease.into_iter().filter(|p| self.lit(*p)))?;
+        let (width, height) = if f.horiz {
+            (folded_size(f.pos, self.width.max(f.pos + 1)), self.height)
         } else {
-            new_paper.insert((p.0, calc_new_pos(p.1, pos)));
+            (self.width, folded_size(f.pos, self.height.max(f.pos + 1)))
+        };
+        let mut cells = vec![false; (width * height) as usize];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.cells[(y * self.width + x) as usize] {
+                    continue;
+                }
+                let (nx, ny) = if f.horiz {
+                    (calc_new_pos(x, f.pos, self.width.max(f.pos + 1)), y)
+                } else {
+                    (x, calc_new_pos(y, f.pos, self.height.max(f.pos + 1)))
+                };
+                cells[(ny * width + nx) as usize] = true;
+            }
         }
+        Ok(Bitmap {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    fn lit(&self, p: (i32, i32)) -> bool {
+        p.0 >= 0
+            && p.1 >= 0
+            && p.0 < self.width
+            && p.1 < self.height
+            && self.cells[(p.1 * self.width + p.0) as usize]
+    }
+
+    fn dots(&self) -> HashSet<(i32, i32)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|p| self.cells[(p.1 * self.width + p.0) as usize])
+            .collect()
     }
-    new_paper
 }
 
 fn draw_paper(current_paper: &HashSet<(i32, i32)>) {
@@ -56,22 +210,58 @@ fn main() {
     let stdin = io::stdin();
     let values: Vec<String> = stdin.lock().lines().map(|input| input.unwrap()).collect();
 
-    let mut current_paper = HashSet::new();
-    for x in values.iter().filter(|x| x.contains(",")) {
-        let c: Vec<&str> = x.split(",").collect();
+    let mut dots = vec![];
+    for x in values.iter().filter(|x| x.contains(',')) {
+        let c: Vec<&str> = x.split(',').collect();
         let p: (i32, i32) = (parse_input(c[0]), parse_input(c[1]));
-        current_paper.insert(p);
-        // println!("{:?}", x);/
+        dots.push(p);
     }
+    let folds: Vec<Fold> = values
+        .iter()
+        .filter(|x| x.contains('='))
+        .map(|x| Fold::parse_input(x))
+        .collect();
 
-    for (i, x) in values.iter().filter(|x| x.contains("=")).enumerate() {
-        let c: Vec<&str> = x.split("=").collect();
-        let pos: i32 = parse_input(c[1]);
-        let horiz = c[0].chars().last().unwrap() == 'x';
-        current_paper = fold(&current_paper, pos, horiz);
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut paper = Paper::new(&dots);
+    let mut bitmap = paper.to_bitmap();
+    for (i, f) in folds.iter().enumerate() {
+        match paper.fold(*f).and_then(|_| bitmap.fold(*f)) {
+            Ok(b) => bitmap = b,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
         if i == 0 {
-            println!("{:?}", current_paper.len());
+            println!("{:?}", paper.origins.len());
         }
     }
+    let current_paper = if args.iter().any(|a| a == "--dense") {
+        bitmap.dots()
+    } else {
+        paper.dots()
+    };
     draw_paper(&current_paper);
+    match aoc_ocr::recognise(&current_paper) {
+        Ok(text) => println!("{}", text),
+        Err(aoc_ocr::OcrError::UnknownGlyphs { text, glyphs }) => {
+            println!("{}", text);
+            for g in glyphs.iter() {
+                eprintln!("unknown glyph {}:", g.index);
+                for row in g.bitmap.iter() {
+                    eprintln!("{}", row);
+                }
+            }
+        }
+        Err(e) => eprintln!("{:?}", e),
+    }
+
+    if args.iter().any(|a| a == "--provenance") {
+        let mut final_dots = current_paper.iter().collect::<Vec<_>>();
+        final_dots.sort_unstable_by_key(|p| (p.1, p.0));
+        for p in final_dots {
+            println!("{:?} <- {:?}", p, paper.provenance(*p));
+        }
+    }
 }
@@ -9,6 +9,8 @@ use std::io::LineWriter;
 use std::fmt::Debug;
 use std::str::FromStr;
 
+use aoc_bignum::BigUint;
+
 fn parse_input<Input, Type>(input: Input) -> Type
 where
     Type: FromStr,
@@ -20,9 +22,12 @@ where
 
 type PolymerPair = (char, char);
 
+/// One insertion step. Pairs with no rule are carried over unchanged and recorded in
+/// `unmatched` (ignoring the ' ' end marker).
 fn expand(
     polymer: &HashMap<PolymerPair, u64>,
     rules: &HashMap<PolymerPair, char>,
+    unmatched: &mut BTreeSet<PolymerPair>,
 ) -> HashMap<PolymerPair, u64> {
     let mut newp = HashMap::new();
     for (&pp, v) in polymer.iter() {
@@ -34,6 +39,9 @@ fn expand(
                 *newp.entry(p2).or_insert(0) += v;
             }
             None => {
+                if pp.1 != ' ' {
+                    unmatched.insert(pp);
+                }
                 *newp.entry(pp).or_insert(0) += v;
             }
         }
@@ -42,37 +50,456 @@ fn expand(
     newp
 }
 
-fn calc_answer(polymer: &HashMap<PolymerPair, u64>) -> u64 {
-    let mut counter = HashMap::new();
+/// Pair counts for a template. The last element is paired with ' ' so that counting the
+/// first element of every pair counts every element.
+fn template_pairs(pchars: &[char]) -> HashMap<PolymerPair, u64> {
+    let mut polymer = HashMap::new();
+    for (i, c) in pchars.iter().enumerate() {
+        let p = (*c, *pchars.get(i + 1).unwrap_or(&' '));
+        *polymer.entry(p).or_insert(0) += 1;
+    }
+    polymer
+}
+
+fn element_counts(polymer: &HashMap<PolymerPair, u64>) -> BTreeMap<char, u64> {
+    let mut counter = BTreeMap::new();
     for (x, v) in polymer.iter() {
         *counter.entry(x.0).or_insert(0) += v;
     }
+    counter
+}
+
+fn calc_answer(polymer: &HashMap<PolymerPair, u64>) -> u64 {
+    let counter = element_counts(polymer);
     let most_common = counter.values().max().unwrap();
     let least_common = counter.values().min().unwrap();
 
     most_common - least_common
 }
 
+/// Numbers the transition matrix can be built from.
+trait Semiring: Clone + PartialEq {
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+}
+
+impl Semiring for BigUint {
+    fn add(&self, other: &BigUint) -> BigUint {
+        BigUint::add(self, other)
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        BigUint::mul(self, other)
+    }
+}
+
+/// Value modulo a prime (any modulus below 2^63 works).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ModP {
+    value: u64,
+    modulus: u64,
+}
+
+impl Semiring for ModP {
+    fn add(&self, other: &ModP) -> ModP {
+        ModP {
+            value: (self.value + other.value) % self.modulus,
+            modulus: self.modulus,
+        }
+    }
+
+    fn mul(&self, other: &ModP) -> ModP {
+        ModP {
+            value: ((self.value as u128 * other.value as u128) % self.modulus as u128) as u64,
+            modulus: self.modulus,
+        }
+    }
+}
+
+impl std::fmt::Display for ModP {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+type Matrix<T> = Vec<Vec<T>>;
+
+fn mat_mul<T: Semiring>(a: &Matrix<T>, b: &Matrix<T>, zero: &T) -> Matrix<T> {
+    let n = a.len();
+    let mut out = vec![vec![zero.clone(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            // transition matrices are mostly zeros
+            if a[i][k] == *zero {
+                continue;
+            }
+            for j in 0..n {
+                if b[k][j] != *zero {
+                    out[i][j] = out[i][j].add(&a[i][k].mul(&b[k][j]));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn mat_pow<T: Semiring>(m: &Matrix<T>, mut power: u64, zero: &T, one: &T) -> Matrix<T> {
+    let n = m.len();
+    let mut result: Matrix<T> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| if i == j { one.clone() } else { zero.clone() })
+                .collect()
+        })
+        .collect();
+    let mut base = m.clone();
+    while power > 0 {
+        if power & 1 == 1 {
+            result = mat_mul(&result, &base, zero);
+        }
+        base = mat_mul(&base, &base, zero);
+        power >>= 1;
+    }
+    result
+}
+
+/// Element counts after any number of steps, found by raising the pair-transition matrix
+/// to that power. `num` converts small counts into the chosen number type; with big
+/// integers the counts roughly double each step, so very large step counts need a modulus.
+fn histogram_after<T, F>(
+    polymer: &HashMap<PolymerPair, u64>,
+    rules: &HashMap<PolymerPair, char>,
+    steps: u64,
+    num: F,
+) -> BTreeMap<char, T>
+where
+    T: Semiring,
+    F: Fn(u64) -> T,
+{
+    // every pair reachable from the template
+    let mut pairs: Vec<PolymerPair> = vec![];
+    let mut index = HashMap::new();
+    let mut queue: VecDeque<PolymerPair> = polymer.keys().copied().collect();
+    while let Some(pp) = queue.pop_front() {
+        if index.contains_key(&pp) {
+            continue;
+        }
+        index.insert(pp, pairs.len());
+        pairs.push(pp);
+        if let Some(&p) = rules.get(&pp) {
+            queue.push_back((pp.0, p));
+            queue.push_back((p, pp.1));
+        }
+    }
+
+    let (zero, one) = (num(0), num(1));
+    let n = pairs.len();
+    // m[to][from] is how many `to` pairs one `from` pair becomes
+    let mut m = vec![vec![zero.clone(); n]; n];
+    for (i, pp) in pairs.iter().enumerate() {
+        match rules.get(pp) {
+            Some(&p) => {
+                for to in [(pp.0, p), (p, pp.1)] {
+                    let j = index[&to];
+                    m[j][i] = m[j][i].add(&one);
+                }
+            }
+            None => m[i][i] = one.clone(),
+        }
+    }
+    let m = mat_pow(&m, steps, &zero, &one);
+
+    let mut counter = BTreeMap::new();
+    for (j, pp) in pairs.iter().enumerate() {
+        let mut total = zero.clone();
+        for (i, from) in pairs.iter().enumerate() {
+            if let Some(&v) = polymer.get(from) {
+                total = total.add(&m[j][i].mul(&num(v)));
+            }
+        }
+        let e = counter.entry(pp.0).or_insert_with(|| zero.clone());
+        *e = e.add(&total);
+    }
+    counter
+}
+
+/// u128 that records overflow instead of wrapping. None is a value of at least 2^128, so
+/// comparisons against a real u128 stay exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checked(Option<u128>);
+
+impl Semiring for Checked {
+    fn add(&self, other: &Checked) -> Checked {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => Checked(a.checked_add(b)),
+            _ => Checked(None),
+        }
+    }
+
+    fn mul(&self, other: &Checked) -> Checked {
+        match (self.0, other.0) {
+            (Some(0), _) | (_, Some(0)) => Checked(Some(0)),
+            (Some(a), Some(b)) => Checked(a.checked_mul(b)),
+            _ => Checked(None),
+        }
+    }
+}
+
+/// How many elements each pair grows into after s steps, not counting its right-hand
+/// element (which belongs to the next pair). A row is kept per step only until the table
+/// stops changing, which happens once every length has either settled or overflowed.
+/// Pairs are numbered so long descents don't have to hash them.
+struct PairLengths {
+    pairs: Vec<PolymerPair>,
+    index: HashMap<PolymerPair, usize>,
+    /// The two pairs each pair turns into, if a rule expands it.
+    children: Vec<Option<(usize, usize)>>,
+    rows: Vec<Vec<Checked>>,
+}
+
+impl PairLengths {
+    fn get(&self, steps: usize, id: usize) -> Checked {
+        self.rows[steps.min(self.rows.len() - 1)][id]
+    }
+}
+
+fn pair_lengths(
+    rules: &HashMap<PolymerPair, char>,
+    pairs: &HashSet<PolymerPair>,
+    steps: usize,
+) -> PairLengths {
+    let pairs = pairs.iter().copied().collect::<Vec<_>>();
+    let index: HashMap<PolymerPair, usize> =
+        pairs.iter().enumerate().map(|(i, pp)| (*pp, i)).collect();
+    let children = pairs
+        .iter()
+        .map(|pp| {
+            rules
+                .get(pp)
+                .map(|&p| (index[&(pp.0, p)], index[&(p, pp.1)]))
+        })
+        .collect::<Vec<_>>();
+    let mut rows = vec![vec![Checked(Some(1)); pairs.len()]];
+    for _ in 1..=steps {
+        let prev = rows.last().unwrap();
+        let next = children
+            .iter()
+            .map(|c| match c {
+                Some((l, r)) => prev[*l].add(&prev[*r]),
+                None => Checked(Some(1)),
+            })
+            .collect::<Vec<_>>();
+        if next == *prev {
+            break;
+        }
+        rows.push(next);
+    }
+    PairLengths {
+        pairs,
+        index,
+        children,
+        rows,
+    }
+}
+
+/// Every pair that can appear when growing the template.
+fn reachable_pairs(pchars: &[char], rules: &HashMap<PolymerPair, char>) -> HashSet<PolymerPair> {
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<PolymerPair> = template_pairs(pchars).into_keys().collect();
+    while let Some(pp) = queue.pop_front() {
+        if !seen.insert(pp) {
+            continue;
+        }
+        if let Some(&p) = rules.get(&pp) {
+            queue.push_back((pp.0, p));
+            queue.push_back((p, pp.1));
+        }
+    }
+    seen
+}
+
+/// Total length after the given steps, taken from the element histogram. Fails when the
+/// length doesn't fit in a u128.
+fn polymer_length(
+    pchars: &[char],
+    rules: &HashMap<PolymerPair, char>,
+    steps: u64,
+) -> Result<u128, String> {
+    let num = |v| Checked(Some(v as u128));
+    histogram_after(&template_pairs(pchars), rules, steps, num)
+        .values()
+        .try_fold(0u128, |acc, v| v.0.and_then(|v| acc.checked_add(v)))
+        .ok_or(format!(
+            "polymer length after {} steps overflows u128",
+            steps
+        ))
+}
+
+/// Element at a 0-based index of the polymer after the given steps, found by descending
+/// through pair lengths rather than building the polymer.
+fn element_at(
+    pchars: &[char],
+    rules: &HashMap<PolymerPair, char>,
+    steps: usize,
+    mut index: u128,
+) -> Option<char> {
+    let lens = pair_lengths(rules, &reachable_pairs(pchars, rules), steps);
+    // overflowed lengths are past any u128 index
+    let within = |index: u128, len: Checked| len.0.is_none_or(|l| index < l);
+    for (i, c) in pchars.iter().enumerate() {
+        let mut id = lens.index[&(*c, *pchars.get(i + 1).unwrap_or(&' '))];
+        let len = lens.get(steps, id);
+        if !within(index, len) {
+            index -= len.0.unwrap();
+            continue;
+        }
+        // past the last row every step descends the same way, so once a (pair, index)
+        // state comes round again the walk is periodic and whole periods can be skipped
+        let settled = lens.rows.len() - 1;
+        let mut seen = HashMap::new();
+        let mut s = steps;
+        while s > 0 {
+            let (left, right) = match lens.children[id] {
+                Some(c) => c,
+                None => break,
+            };
+            if s > settled {
+                if let Some(prev) = seen.insert((id, index), s) {
+                    let period = prev - s;
+                    s -= (s - settled) / period * period;
+                }
+            }
+            let len = lens.get(s - 1, left);
+            if within(index, len) {
+                id = left;
+            } else {
+                index -= len.0.unwrap();
+                id = right;
+            }
+            s -= 1;
+        }
+        return Some(lens.pairs[id].0);
+    }
+    None
+}
+
+/// Rules that fire at some point during the given steps, and pairs that turn up with no rule
+/// to expand them (ignoring the ' ' end marker).
+fn rule_coverage(
+    pchars: &[char],
+    rules: &HashMap<PolymerPair, char>,
+    steps: usize,
+) -> (HashSet<PolymerPair>, HashSet<PolymerPair>) {
+    let mut fired = HashSet::new();
+    let mut unmatched = HashSet::new();
+    let mut present: BTreeSet<PolymerPair> = template_pairs(pchars).into_keys().collect();
+    // only which pairs are present matters, so stop once that set repeats
+    let mut seen = HashSet::new();
+    for _ in 0..steps {
+        if !seen.insert(present.clone()) {
+            break;
+        }
+        let mut next = BTreeSet::new();
+        for &pp in present.iter() {
+            match rules.get(&pp) {
+                Some(&p) => {
+                    fired.insert(pp);
+                    next.insert((pp.0, p));
+                    next.insert((p, pp.1));
+                }
+                None => {
+                    if pp.1 != ' ' {
+                        unmatched.insert(pp);
+                    }
+                    next.insert(pp);
+                }
+            }
+        }
+        present = next;
+    }
+    (fired, unmatched)
+}
+
 fn main() {
     let stdin = io::stdin();
     let values: Vec<String> = stdin.lock().lines().map(|input| input.unwrap()).collect();
     let pchars: Vec<char> = values[0].chars().collect();
-    let mut polymer = HashMap::new();
-    for (i, c) in pchars.iter().enumerate() {
-        let p = (*c, *pchars.get(i + 1).unwrap_or(&' '));
-        *polymer.entry(p).or_insert(0) += 1;
-    }
+    let mut polymer = template_pairs(&pchars);
     let mut rules = HashMap::new();
     for x in &values[2..] {
         let c: Vec<char> = x.chars().collect();
         rules.insert((c[0], c[1]), *c.last().unwrap());
     }
+    let mut unmatched = BTreeSet::new();
     for _ in 0..10 {
-        polymer = expand(&polymer, &rules);
+        polymer = expand(&polymer, &rules, &mut unmatched);
     }
     println!("{}", calc_answer(&polymer));
     for _ in 10..40 {
-        polymer = expand(&polymer, &rules);
+        polymer = expand(&polymer, &rules, &mut unmatched);
     }
     println!("{}", calc_answer(&polymer));
+    for pp in unmatched {
+        eprintln!("warning: no rule for pair {}{}", pp.0, pp.1);
+    }
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("missing argument value"))
+    };
+    if let Some(steps) = arg("--steps") {
+        // counts start from the template again, not from the 40 steps above
+        let template = template_pairs(&pchars);
+        let steps: u64 = parse_input(steps);
+        match arg("--mod") {
+            Some(modulus) => {
+                let modulus: u64 = parse_input(modulus);
+                let num = |value| ModP {
+                    value: value % modulus,
+                    modulus,
+                };
+                for (c, v) in histogram_after(&template, &rules, steps, num) {
+                    println!("{}: {}", c, v);
+                }
+            }
+            None => {
+                for (c, v) in histogram_after(&template, &rules, steps, BigUint::from_u64) {
+                    println!("{}: {}", c, v);
+                }
+            }
+        }
+
+        if args.iter().any(|a| a == "--length") {
+            match polymer_length(&pchars, &rules, steps) {
+                Ok(len) => println!("length: {}", len),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        let steps = steps as usize;
+        if let Some(index) = arg("--index") {
+            let index: u128 = parse_input(index);
+            match element_at(&pchars, &rules, steps, index) {
+                Some(c) => println!("element {}: {}", index, c),
+                None => println!("element {}: past the end", index),
+            }
+        }
+        if args.iter().any(|a| a == "--coverage") {
+            let (fired, unmatched) = rule_coverage(&pchars, &rules, steps);
+            let mut unused = rules
+                .keys()
+                .filter(|pp| !fired.contains(pp))
+                .collect::<Vec<_>>();
+            unused.sort_unstable();
+            for pp in unused {
+                println!("never fires: {}{} -> {}", pp.0, pp.1, rules[pp]);
+            }
+            let mut unmatched = unmatched.into_iter().collect::<Vec<_>>();
+            unmatched.sort_unstable();
+            for pp in unmatched {
+                eprintln!("warning: no rule for pair {}{}", pp.0, pp.1);
+            }
+        }
+    }
 }
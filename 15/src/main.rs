@@ -19,30 +19,257 @@ where
 
 const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (-1, 0), (0, -1)];
 
-fn find_risk_path(grid: &HashMap<(i32, i32), i32>, w: i32, h: i32) -> i32 {
+/// Anything pathfinding can run over: a w x h area of cells with a risk each.
+trait RiskMap {
+    fn size(&self) -> (i32, i32);
+    /// Risk of entering p, None off the map.
+    fn risk(&self, p: (i32, i32)) -> Option<i32>;
+    fn max_risk(&self) -> i32;
+}
+
+impl RiskMap for HashMap<(i32, i32), i32> {
+    fn size(&self) -> (i32, i32) {
+        (
+            self.keys().map(|p| p.0 + 1).max().unwrap_or(0),
+            self.keys().map(|p| p.1 + 1).max().unwrap_or(0),
+        )
+    }
+
+    fn risk(&self, p: (i32, i32)) -> Option<i32> {
+        self.get(&p).copied()
+    }
+
+    fn max_risk(&self) -> i32 {
+        self.values().copied().max().unwrap_or(1)
+    }
+}
+
+/// How a tile's risks change with its distance (in tiles) from the original.
+#[derive(Clone, Copy)]
+struct WrapRule {
+    /// Added per tile right or down.
+    increment: i32,
+    /// Risks wrap round to `min` once they pass `max`.
+    min: i32,
+    max: i32,
+}
+
+impl WrapRule {
+    fn apply(&self, risk: i32, distance: i32) -> i32 {
+        let range = self.max - self.min + 1;
+        (risk + distance * self.increment - self.min).rem_euclid(range) + self.min
+    }
+}
+
+/// The base map repeated `tiles` times in each direction, with risks worked out on demand
+/// so nothing bigger than the base is stored.
+struct TiledGrid<'a, G: RiskMap> {
+    base: &'a G,
+    base_size: (i32, i32),
+    tiles: (i32, i32),
+    wrap: WrapRule,
+}
+
+impl<'a, G: RiskMap> TiledGrid<'a, G> {
+    /// Fails if the tiled map is too wide or tall for i32 coordinates.
+    fn new(
+        base: &'a G,
+        tiles: (i32, i32),
+        wrap: WrapRule,
+    ) -> Result<TiledGrid<'a, G>, SearchError> {
+        let base_size = base.size();
+        let width = base_size.0 as i64 * tiles.0 as i64;
+        let height = base_size.1 as i64 * tiles.1 as i64;
+        if width > i32::MAX as i64 || height > i32::MAX as i64 {
+            return Err(SearchError::TooLarge { width, height });
+        }
+        Ok(TiledGrid {
+            base,
+            base_size,
+            tiles,
+            wrap,
+        })
+    }
+}
+
+impl<G: RiskMap> RiskMap for TiledGrid<'_, G> {
+    fn size(&self) -> (i32, i32) {
+        (
+            self.base_size.0 * self.tiles.0,
+            self.base_size.1 * self.tiles.1,
+        )
+    }
+
+    fn risk(&self, p: (i32, i32)) -> Option<i32> {
+        let (w, h) = self.size();
+        if p.0 < 0 || p.1 < 0 || p.0 >= w || p.1 >= h {
+            return None;
+        }
+        let (bw, bh) = self.base_size;
+        let risk = self.base.risk((p.0 % bw, p.1 % bh))?;
+        Some(self.wrap.apply(risk, p.0 / bw + p.1 / bh))
+    }
+
+    fn max_risk(&self) -> i32 {
+        if self.tiles == (1, 1) {
+            self.base.max_risk()
+        } else {
+            self.wrap.max
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    /// Dijkstra with a binary heap.
+    Dijkstra,
+    /// A* using Manhattan distance to the goal, which never overestimates as every step
+    /// costs at least 1.
+    AStar,
+    /// Dijkstra with a ring of buckets, one per distance, as risks are only 1-9.
+    Dial,
+}
+
+#[derive(Debug)]
+enum SearchError {
+    /// A map of this width and height has more cells than can be indexed or allocated.
+    TooLarge { width: i64, height: i64 },
+    /// The goal can't be reached from the start.
+    NoPath,
+}
+
+struct RiskPath {
+    risk: i32,
+    /// Every cell from the start to the goal inclusive.
+    path: Vec<(i32, i32)>,
+}
+
+/// Marks a cell in `prev` that wasn't reached from a neighbour.
+const NO_PREV: u8 = u8::MAX;
+
+/// Walks back from the goal; `prev` holds, for each cell, the index into DIRECTIONS of the
+/// step that reached it.
+fn reconstruct(prev: &[u8], w: usize, goal: (i32, i32)) -> Vec<(i32, i32)> {
+    let mut path = vec![goal];
+    let mut p = goal;
+    while prev[p.1 as usize * w + p.0 as usize] != NO_PREV {
+        let d = DIRECTIONS[prev[p.1 as usize * w + p.0 as usize] as usize];
+        p = (p.0 - d.0, p.1 - d.1);
+        path.push(p);
+    }
+    path.reverse();
+    path
+}
+
+/// Vec of `len` copies of `value`, or None if that much memory can't be had.
+fn try_filled<T: Clone>(len: usize, value: T) -> Option<Vec<T>> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(len).ok()?;
+    v.resize(len, value);
+    Some(v)
+}
+
+/// Search state is kept in flat arrays indexed by y * w + x, 5 bytes a cell, so even a
+/// heavily tiled map only costs its area rather than a hash entry per visited cell.
+fn find_risk_path<G: RiskMap>(grid: &G, algorithm: Algorithm) -> Result<RiskPath, SearchError> {
+    let (w, h) = grid.size();
+    let (uw, uh) = (w as usize, h as usize);
+    let idx = |p: (i32, i32)| p.1 as usize * uw + p.0 as usize;
     let start = (0, 0);
-    let mut mins = HashMap::new();
-    let mut explored = VecDeque::new();
-    explored.push_back(start);
-    mins.insert(start, 0);
-    while let Some(pos) = explored.pop_front() {
-        let risk = mins[&pos];
-        for d in DIRECTIONS.iter() {
+    let goal = (w - 1, h - 1);
+    let heuristic = |p: (i32, i32)| match algorithm {
+        Algorithm::AStar => (goal.0 - p.0).abs() + (goal.1 - p.1).abs(),
+        _ => 0,
+    };
+    let too_large = || SearchError::TooLarge {
+        width: w as i64,
+        height: h as i64,
+    };
+    let area = uw.checked_mul(uh).ok_or_else(too_large)?;
+    let mut mins = try_filled(area, u32::MAX).ok_or_else(too_large)?;
+    let mut prev = try_filled(area, NO_PREV).ok_or_else(too_large)?;
+    mins[idx(start)] = 0;
+
+    let mut heap = BinaryHeap::new();
+    // Dial's buckets, indexed by risk modulo the bucket count
+    let max_risk = grid.max_risk().max(1);
+    let nbuckets = max_risk as usize + 1;
+    let mut buckets = vec![vec![]; nbuckets];
+    let mut current = 0;
+    let mut queued = 0;
+    match algorithm {
+        Algorithm::Dial => {
+            buckets[0].push(start);
+            queued += 1;
+        }
+        _ => heap.push(std::cmp::Reverse((heuristic(start), start))),
+    }
+
+    loop {
+        let pos = if algorithm == Algorithm::Dial {
+            if queued == 0 {
+                return Err(SearchError::NoPath);
+            }
+            while buckets[current % nbuckets].is_empty() {
+                current += 1;
+            }
+            queued -= 1;
+            let bucket = current % nbuckets;
+            let pos = buckets[bucket].pop().unwrap();
+            // stale entry, a cheaper route to this cell was queued later
+            if mins[idx(pos)] != current as u32 {
+                continue;
+            }
+            pos
+        } else {
+            let std::cmp::Reverse((estimate, pos)) = heap.pop().ok_or(SearchError::NoPath)?;
+            if (estimate - heuristic(pos)) as u32 > mins[idx(pos)] {
+                continue;
+            }
+            pos
+        };
+        if pos == goal {
+            return Ok(RiskPath {
+                risk: mins[idx(goal)] as i32,
+                path: reconstruct(&prev, uw, goal),
+            });
+        }
+        let risk = mins[idx(pos)];
+        for (dir, d) in DIRECTIONS.iter().enumerate() {
             let np = (d.0 + pos.0, d.1 + pos.1);
-            match grid.get(&np) {
-                None => {}
-                Some(extra_risk) => {
-                    let new_risk = risk + extra_risk;
-                    let mr = mins.entry(np).or_insert(std::i32::MAX);
-                    if new_risk < *mr {
-                        *mr = new_risk;
-                        explored.push_back(np);
+            if let Some(extra_risk) = grid.risk(np) {
+                let new_risk = risk + extra_risk as u32;
+                if new_risk < mins[idx(np)] {
+                    mins[idx(np)] = new_risk;
+                    prev[idx(np)] = dir as u8;
+                    if algorithm == Algorithm::Dial {
+                        buckets[new_risk as usize % nbuckets].push(np);
+                        queued += 1;
+                    } else {
+                        heap.push(std::cmp::Reverse((new_risk as i32 + heuristic(np), np)));
                     }
                 }
             }
         }
     }
-    mins[&(w - 1, h - 1)]
+}
+
+/// The grid with every cell off the path replaced by '.'.
+fn draw_path<G: RiskMap>(grid: &G, path: &[(i32, i32)]) -> String {
+    let (w, h) = grid.size();
+    let on_path: HashSet<_> = path.iter().collect();
+    let mut out = String::new();
+    for y in 0..h {
+        for x in 0..w {
+            if on_path.contains(&(x, y)) {
+                out += &grid.risk((x, y)).unwrap().to_string();
+            } else {
+                out.push('.');
+            }
+        }
+        out.push('\n');
+    }
+    out
 }
 
 fn main() {
@@ -56,25 +283,51 @@ fn main() {
             grid.insert(p, c.to_digit(10).unwrap() as i32);
         }
     }
-    let w = values[0].len() as i32;
-    let h = values.len() as i32;
-    let p1ans = find_risk_path(&grid, w, h);
-
-    println!("{:?}", p1ans);
-
-    let nw = w * 5;
-    let nh = h * 5;
-    for x in 0..nw {
-        for y in 0..nh {
-            let inc = x / w + y / h;
-            let mut new_risk = grid[&(x % w, y % h)] + inc;
-            while new_risk > 9 {
-                new_risk -= 9;
-            }
-            grid.insert((x, y), new_risk);
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let algorithm = match args.iter().position(|a| a == "--algorithm") {
+        Some(i) => match args.get(i + 1).map(|a| a.as_str()) {
+            Some("dijkstra") => Algorithm::Dijkstra,
+            Some("astar") => Algorithm::AStar,
+            Some("dial") => Algorithm::Dial,
+            a => panic!("unknown algorithm {:?}", a),
+        },
+        None => Algorithm::Dijkstra,
+    };
+    let show_path = args.iter().any(|a| a == "--show-path");
+
+    let tiles = match args.iter().position(|a| a == "--tiles") {
+        Some(i) => {
+            let t = args
+                .get(i + 1)
+                .expect("--tiles needs x,y")
+                .split(',')
+                .map(parse_input)
+                .collect::<Vec<i32>>();
+            (t[0], t[1])
         }
+        None => (5, 5),
+    };
+
+    let exit = |e: SearchError| -> ! {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    };
+    let p1ans = find_risk_path(&grid, algorithm).unwrap_or_else(|e| exit(e));
+    println!("{:?}", p1ans.risk);
+    if show_path {
+        print!("{}", draw_path(&grid, &p1ans.path));
     }
-    let p2ans = find_risk_path(&grid, nw, nh);
 
-    println!("{:?}", p2ans);
+    let wrap = WrapRule {
+        increment: 1,
+        min: 1,
+        max: 9,
+    };
+    let tiled = TiledGrid::new(&grid, tiles, wrap).unwrap_or_else(|e| exit(e));
+    let p2ans = find_risk_path(&tiled, algorithm).unwrap_or_else(|e| exit(e));
+    println!("{:?}", p2ans.risk);
+    if show_path {
+        print!("{}", draw_path(&tiled, &p2ans.path));
+    }
 }
@@ -5,9 +5,9 @@ use std::collections::*;
 use std::io;
 use std::io::prelude::*;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum PacketType {
-    Literal(u64),
+    Literal(u128),
     Sum,
     Product,
     Min,
@@ -17,71 +17,285 @@ enum PacketType {
     Equal,
 }
 
-struct InputData {
-    s: String,
-    start: usize,
+#[derive(Debug, PartialEq)]
+enum DecodeError {
+    /// A character that isn't a hex digit, by its position in the text.
+    InvalidHex { index: usize, c: char },
+    /// A field ran past the end of the transmission.
+    Truncated {
+        offset: usize,
+        wanted: usize,
+        left: usize,
+    },
+    /// Sub-packets overran their operator's bit length, ending at `offset` instead of `end`.
+    LengthOverrun { offset: usize, end: usize },
+    /// Non-zero bits after the outermost packet.
+    TrailingData { offset: usize },
+    /// A literal, starting at `offset`, too big for 128 bits.
+    LiteralOverflow { offset: usize },
+    /// The packet starting at `offset` is nested more than MAX_DEPTH operators deep.
+    TooDeep { offset: usize },
+}
+
+/// Deepest nesting of operators accepted when decoding. Decoding, evaluating and printing
+/// all recurse, so this keeps any decoded transmission well within the stack, even the
+/// 2MB of a spawned thread in a debug build.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, PartialEq)]
+enum EvalError {
+    /// An operator with no sub-packets to work on.
+    NoOperands(PacketType),
+    /// A comparison that didn't get exactly two sub-packets.
+    NotBinary { op: PacketType, operands: usize },
+    /// The result doesn't fit in 128 bits.
+    Overflow(PacketType),
+}
+
+/// Reads big-endian bit fields out of the bytes of a transmission.
+struct BitReader {
+    bytes: Vec<u8>,
+    /// Position of the next bit to read.
+    pos: usize,
+    len: usize,
+    /// Operators currently open around the packet being read.
+    depth: usize,
 }
 
-impl InputData {
-    fn new(s: String) -> InputData {
-        InputData { s, start: 0 }
+impl BitReader {
+    fn new(bytes: Vec<u8>) -> BitReader {
+        let len = bytes.len() * 8;
+        BitReader {
+            bytes,
+            pos: 0,
+            len,
+            depth: 0,
+        }
+    }
+
+    fn from_hex(s: &str) -> Result<BitReader, DecodeError> {
+        let digits = s
+            .trim()
+            .chars()
+            .enumerate()
+            .map(|(index, c)| {
+                c.to_digit(16)
+                    .map(|d| d as u8)
+                    .ok_or(DecodeError::InvalidHex { index, c })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let bytes = digits
+            .chunks(2)
+            .map(|c| c[0] << 4 | c.get(1).unwrap_or(&0))
+            .collect();
+        let mut reader = BitReader::new(bytes);
+        reader.len = digits.len() * 4;
+        Ok(reader)
+    }
+
+    fn remaining(&self) -> usize {
+        self.len - self.pos
     }
 
-    fn extract_data(&mut self, bits: usize) -> u64 {
-        let res = u64::from_str_radix(&self.s[self.start..(self.start + bits)], 2).unwrap();
-        self.start += bits;
-        res
+    /// Reads the next `bits` bits (at most 64) as an unsigned number.
+    fn read(&mut self, bits: usize) -> Result<u64, DecodeError> {
+        if bits > self.remaining() {
+            return Err(DecodeError::Truncated {
+                offset: self.pos,
+                wanted: bits,
+                left: self.remaining(),
+            });
+        }
+        let mut res = 0u64;
+        let mut bits_left = bits;
+        while bits_left > 0 {
+            let used = self.pos % 8;
+            let take = (8 - used).min(bits_left);
+            let byte = self.bytes[self.pos / 8] as u64;
+            res = (res << take) | ((byte >> (8 - used - take)) & ((1 << take) - 1));
+            self.pos += take;
+            bits_left -= take;
+        }
+        Ok(res)
     }
 
-    fn extract_packets_by_length(&mut self, length: usize) -> Vec<Packet> {
+    fn extract_packets_by_length(&mut self, length: usize) -> Result<Vec<Packet>, DecodeError> {
         let mut v = Vec::new();
-        let start = self.start;
-        while (self.start - start) != length {
-            v.push(Packet::parse_input(self));
+        let end = self.pos + length;
+        if end > self.len {
+            return Err(DecodeError::Truncated {
+                offset: self.pos,
+                wanted: length,
+                left: self.remaining(),
+            });
+        }
+        while self.pos < end {
+            v.push(Packet::parse_input(self)?);
         }
-        v
+        if self.pos != end {
+            return Err(DecodeError::LengthOverrun {
+                offset: self.pos,
+                end,
+            });
+        }
+        Ok(v)
     }
 
-    fn extract_packets_by_count(&mut self, count: usize) -> Vec<Packet> {
+    fn extract_packets_by_count(&mut self, count: usize) -> Result<Vec<Packet>, DecodeError> {
         (0..count).map(|_| Packet::parse_input(self)).collect()
     }
+
+    /// Checks that everything after the current position is zero padding.
+    fn expect_padding(&mut self) -> Result<(), DecodeError> {
+        while self.remaining() > 0 {
+            let offset = self.pos;
+            let chunk = self.remaining().min(64);
+            if self.read(chunk)? != 0 {
+                return Err(DecodeError::TrailingData { offset });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds big-endian bit fields into bytes, the reverse of `BitReader`.
+#[derive(Debug, PartialEq)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Number of bits written; the rest of the last byte is zero.
+    len: usize,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: vec![],
+            len: 0,
+        }
+    }
+
+    /// Appends the low `bits` bits (at most 64) of `value`.
+    fn write(&mut self, bits: usize, value: u64) {
+        let mut bits_left = bits;
+        while bits_left > 0 {
+            let used = self.len % 8;
+            if used == 0 {
+                self.bytes.push(0);
+            }
+            let take = (8 - used).min(bits_left);
+            let chunk = (value >> (bits_left - take)) & ((1 << take) - 1);
+            *self.bytes.last_mut().unwrap() |= (chunk << (8 - used - take)) as u8;
+            self.len += take;
+            bits_left -= take;
+        }
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        for (i, &byte) in other.bytes.iter().enumerate() {
+            let bits = (other.len - i * 8).min(8);
+            self.write(bits, (byte >> (8 - bits)) as u64);
+        }
+    }
+
+    /// The bits as uppercase hex, zero padded to a whole number of digits.
+    fn to_hex(&self) -> String {
+        (0..self.len.div_ceil(4))
+            .map(|i| {
+                let digit = (self.bytes[i / 2] >> (4 - i % 2 * 4)) & 0xf;
+                format!("{:X}", digit)
+            })
+            .collect()
+    }
+}
+
+/// How an operator says where its sub-packets end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LengthType {
+    /// 15-bit total length of the sub-packets in bits.
+    Bits,
+    /// 11-bit number of sub-packets.
+    Count,
+}
+
+#[derive(Debug, PartialEq)]
+enum EncodeError {
+    VersionTooLarge(u8),
+    /// Sub-packets too long for the 15-bit length field.
+    TooManyBits(usize),
+    /// Too many sub-packets for the 11-bit count field.
+    TooManyPackets(usize),
+    /// Literals carry no sub-packets and operators need a length type.
+    Malformed,
 }
 
 #[derive(Clone, Debug)]
 struct Packet {
     version: u8,
     packet_type: PacketType,
+    /// None for literals.
+    length_type: Option<LengthType>,
     sub: Vec<Packet>,
+    /// Bit offsets the packet was decoded from, empty for packets built in code.
+    span: std::ops::Range<usize>,
+}
+
+/// Packets compare by content, wherever they sat in a transmission.
+impl PartialEq for Packet {
+    fn eq(&self, other: &Packet) -> bool {
+        self.version == other.version
+            && self.packet_type == other.packet_type
+            && self.length_type == other.length_type
+            && self.sub == other.sub
+    }
 }
 
 impl Packet {
-    fn parse_input(input: &mut InputData) -> Packet {
-        let version = input.extract_data(3) as u8;
-        let ptype = input.extract_data(3) as u8;
+    /// Decodes a whole hex transmission, which must hold one packet and then only zero bits.
+    fn decode(hex: &str) -> Result<Packet, DecodeError> {
+        let mut input = BitReader::from_hex(hex)?;
+        let packet = Packet::parse_input(&mut input)?;
+        input.expect_padding()?;
+        Ok(packet)
+    }
+
+    fn parse_input(input: &mut BitReader) -> Result<Packet, DecodeError> {
+        let offset = input.pos;
+        let version = input.read(3)? as u8;
+        let ptype = input.read(3)? as u8;
         let packet_type;
         let sub;
+        let mut length_type = None;
         if ptype == 4 {
-            let mut val: u64 = 0;
+            let mut val: u128 = 0;
             loop {
-                let t = input.extract_data(1);
-                val += input.extract_data(4);
-                if t > 0 {
-                    val *= 16;
-                } else {
+                let t = input.read(1)?;
+                let group = input.read(4)? as u128;
+                val = val
+                    .checked_mul(16)
+                    .map(|v| v | group)
+                    .ok_or(DecodeError::LiteralOverflow { offset })?;
+                if t == 0 {
                     break;
                 }
             }
             sub = Vec::new();
             packet_type = PacketType::Literal(val);
         } else {
-            let sptype = input.extract_data(1);
+            if input.depth == MAX_DEPTH {
+                return Err(DecodeError::TooDeep { offset });
+            }
+            input.depth += 1;
+            let sptype = input.read(1)?;
             if sptype == 0 {
-                let bitlength = input.extract_data(15) as usize;
-                sub = input.extract_packets_by_length(bitlength);
+                let bitlength = input.read(15)? as usize;
+                sub = input.extract_packets_by_length(bitlength)?;
+                length_type = Some(LengthType::Bits);
             } else {
-                let pcount = input.extract_data(11) as usize;
-                sub = input.extract_packets_by_count(pcount);
+                let pcount = input.read(11)? as usize;
+                sub = input.extract_packets_by_count(pcount)?;
+                length_type = Some(LengthType::Count);
             }
+            input.depth -= 1;
             packet_type = match ptype {
                 0 => PacketType::Sum,
                 1 => PacketType::Product,
@@ -93,11 +307,101 @@ impl Packet {
                 _ => panic!("{:?} unknown packet type", ptype),
             };
         }
+        Ok(Packet {
+            version,
+            packet_type,
+            length_type,
+            sub,
+            span: offset..input.pos,
+        })
+    }
+
+    fn literal(version: u8, value: u128) -> Packet {
+        Packet {
+            version,
+            packet_type: PacketType::Literal(value),
+            length_type: None,
+            sub: vec![],
+            span: 0..0,
+        }
+    }
+
+    fn operator(
+        version: u8,
+        packet_type: PacketType,
+        length_type: LengthType,
+        sub: Vec<Packet>,
+    ) -> Packet {
         Packet {
             version,
             packet_type,
+            length_type: Some(length_type),
             sub,
+            span: 0..0,
+        }
+    }
+
+    fn type_id(&self) -> u8 {
+        match self.packet_type {
+            PacketType::Sum => 0,
+            PacketType::Product => 1,
+            PacketType::Min => 2,
+            PacketType::Max => 3,
+            PacketType::Literal(_) => 4,
+            PacketType::Greater => 5,
+            PacketType::Less => 6,
+            PacketType::Equal => 7,
+        }
+    }
+
+    /// The packet's bits, without any padding. Literals use the fewest 4-bit groups that
+    /// hold their value.
+    fn encode(&self) -> Result<BitWriter, EncodeError> {
+        if self.version > 7 {
+            return Err(EncodeError::VersionTooLarge(self.version));
+        }
+        let mut out = BitWriter::new();
+        out.write(3, self.version as u64);
+        out.write(3, self.type_id() as u64);
+        match (&self.packet_type, self.length_type) {
+            (PacketType::Literal(value), None) if self.sub.is_empty() => {
+                let groups = (128 - value.leading_zeros() as usize).div_ceil(4);
+                for g in (0..groups.max(1)).rev() {
+                    out.write(1, (g > 0) as u64);
+                    out.write(4, ((value >> (g * 4)) & 0xf) as u64);
+                }
+            }
+            (PacketType::Literal(_), _) | (_, None) => return Err(EncodeError::Malformed),
+            (_, Some(length_type)) => {
+                let mut sub = BitWriter::new();
+                for p in self.sub.iter() {
+                    sub.append(&p.encode()?);
+                }
+                match length_type {
+                    LengthType::Bits if sub.len >= 1 << 15 => {
+                        return Err(EncodeError::TooManyBits(sub.len))
+                    }
+                    LengthType::Bits => {
+                        out.write(1, 0);
+                        out.write(15, sub.len as u64);
+                    }
+                    LengthType::Count if self.sub.len() >= 1 << 11 => {
+                        return Err(EncodeError::TooManyPackets(self.sub.len()))
+                    }
+                    LengthType::Count => {
+                        out.write(1, 1);
+                        out.write(11, self.sub.len() as u64);
+                    }
+                }
+                out.append(&sub);
+            }
         }
+        Ok(out)
+    }
+
+    /// The packet as a hex transmission, zero padded to a whole number of hex digits.
+    fn to_hex(&self) -> Result<String, EncodeError> {
+        Ok(self.encode()?.to_hex())
     }
 
     fn version_sum(&self) -> u64 {
@@ -106,37 +410,288 @@ impl Packet {
         value
     }
 
-    fn value(&self) -> u64 {
+    fn value(&self) -> Result<u128, EvalError> {
+        let op = || self.packet_type.clone();
+        if let PacketType::Literal(p) = self.packet_type {
+            return Ok(p);
+        }
+        let values = self
+            .sub
+            .iter()
+            .map(|x| x.value())
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.is_empty() {
+            return Err(EvalError::NoOperands(op()));
+        }
+        let binary = || match values[..] {
+            [a, b] => Ok((a, b)),
+            _ => Err(EvalError::NotBinary {
+                op: op(),
+                operands: values.len(),
+            }),
+        };
+        match self.packet_type {
+            PacketType::Equal => binary().map(|(a, b)| (a == b) as u128),
+            PacketType::Greater => binary().map(|(a, b)| (a > b) as u128),
+            PacketType::Less => binary().map(|(a, b)| (a < b) as u128),
+            PacketType::Literal(_) => unreachable!(),
+            PacketType::Max => Ok(*values.iter().max().unwrap()),
+            PacketType::Min => Ok(*values.iter().min().unwrap()),
+            PacketType::Product => values
+                .iter()
+                .try_fold(1u128, |acc, &v| acc.checked_mul(v))
+                .ok_or(EvalError::Overflow(op())),
+            PacketType::Sum => values
+                .iter()
+                .try_fold(0u128, |acc, &v| acc.checked_add(v))
+                .ok_or(EvalError::Overflow(op())),
+        }
+    }
+
+    /// The operator's name, or the literal's value.
+    fn name(&self) -> String {
         match self.packet_type {
-            PacketType::Equal => (self.sub[0].value() == self.sub[1].value()) as u64,
-            PacketType::Greater => (self.sub[0].value() > self.sub[1].value()) as u64,
-            PacketType::Less => (self.sub[0].value() < self.sub[1].value()) as u64,
-            PacketType::Literal(p) => p,
-            PacketType::Max => self.sub.iter().map(|x| x.value()).max().unwrap(),
-            PacketType::Min => self.sub.iter().map(|x| x.value()).min().unwrap(),
-            PacketType::Product => self.sub.iter().map(|x| x.value()).product::<u64>(),
-            PacketType::Sum => self.sub.iter().map(|x| x.value()).sum::<u64>(),
+            PacketType::Literal(v) => v.to_string(),
+            PacketType::Sum => "sum".to_string(),
+            PacketType::Product => "product".to_string(),
+            PacketType::Min => "min".to_string(),
+            PacketType::Max => "max".to_string(),
+            PacketType::Greater => "gt".to_string(),
+            PacketType::Less => "lt".to_string(),
+            PacketType::Equal => "eq".to_string(),
+        }
+    }
+
+    /// Version, type id, length type and bit offsets, e.g. `v=1 type=0 len=bits @0..84`.
+    fn annotation(&self) -> String {
+        let mut s = format!("v={} type={}", self.version, self.type_id());
+        match self.length_type {
+            Some(LengthType::Bits) => s += " len=bits",
+            Some(LengthType::Count) => s += " len=count",
+            None => {}
         }
+        s + &format!(" @{}..{}", self.span.start, self.span.end)
+    }
+
+    /// One node per line, children indented under their operator, annotations as `;` comments.
+    fn to_tree(&self, annotate: bool) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0, annotate);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize, annotate: bool) {
+        let line = format!("{}{}", "  ".repeat(depth), self.name());
+        if annotate {
+            *out += &format!("{:<24} ; {}\n", line, self.annotation());
+        } else {
+            *out += &line;
+            out.push('\n');
+        }
+        for p in self.sub.iter() {
+            p.write_tree(out, depth + 1, annotate);
+        }
+    }
+
+    /// The packet as an S-expression like `(sum (product 6 9) (min 7 8 9))`, with annotations
+    /// as `#|...|#` comments after each node's name.
+    fn to_sexpr(&self, annotate: bool) -> String {
+        let mut s = self.name();
+        if annotate {
+            s += &format!(" #|{}|#", self.annotation());
+        }
+        if let PacketType::Literal(_) = self.packet_type {
+            return s;
+        }
+        for p in self.sub.iter() {
+            s.push(' ');
+            s += &p.to_sexpr(annotate);
+        }
+        format!("({})", s)
     }
 }
 
 fn main() {
-    let stdin = io::stdin();
-    let values: Vec<String> = stdin
-        .lock()
-        .lines()
-        .map(|input| {
-            let mut s = String::new();
-            for c in input.unwrap().chars() {
-                s += &format!("{:04b}", c.to_digit(16).unwrap());
+    let mut hex = String::new();
+    io::stdin().read_to_string(&mut hex).unwrap();
+    let p = match Packet::decode(&hex) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("{:?}", p.version_sum());
+    match p.value() {
+        Ok(v) => println!("{:?}", v),
+        Err(e) => eprintln!("{:?}", e),
+    }
+
+    let annotate = std::env::args().any(|a| a == "--annotate");
+    if std::env::args().any(|a| a == "--tree") {
+        print!("{}", p.to_tree(annotate));
+    }
+    if std::env::args().any(|a| a == "--sexpr") {
+        println!("{}", p.to_sexpr(annotate));
+    }
+
+    if std::env::args().any(|a| a == "--encode") {
+        let hex = match p.to_hex() {
+            Ok(hex) => hex,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        };
+        println!("{}", hex);
+        // the re-encoded transmission must decode to the same tree
+        match Packet::decode(&hex) {
+            Ok(q) if q == p => {}
+            Ok(q) => {
+                eprintln!("round trip changed the packet:");
+                let (old, new) = (p.to_tree(true), q.to_tree(true));
+                for (a, b) in old.lines().zip(new.lines()) {
+                    if a != b {
+                        eprintln!("- {}\n+ {}", a, b);
+                    }
+                }
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
             }
+        }
+    }
+}
 
-            s
-        })
-        .collect();
-    let mut input = InputData::new(values[0].clone());
-    let p = input.extract_packets_by_count(1);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(p: &Packet) -> Packet {
+        Packet::decode(&p.to_hex().unwrap()).unwrap()
+    }
 
-    println!("{:?}", p[0].version_sum());
-    println!("{:?}", p[0].value());
+    #[test]
+    fn examples_round_trip() {
+        let examples = [
+            ("D2FE28", 6, 2021),
+            ("38006F45291200", 9, 1),
+            ("EE00D40C823060", 14, 3),
+            ("8A004A801A8002F478", 16, 15),
+            ("620080001611562C8802118E34", 12, 46),
+            ("C0015000016115A2E0802F182340", 23, 46),
+            ("A0016C880162017C3686B18A3D4780", 31, 54),
+            ("C200B40A82", 14, 3),
+            ("04005AC33890", 8, 54),
+            ("880086C3E88112", 15, 7),
+            ("CE00C43D881120", 11, 9),
+            ("D8005AC2A8F0", 13, 1),
+            ("F600BC2D8F", 19, 0),
+            ("9C005AC2F8F0", 16, 0),
+            ("9C0141080250320F1802104A08", 20, 1),
+        ];
+        for (hex, version_sum, value) in examples {
+            let p = Packet::decode(hex).unwrap();
+            assert_eq!(p.version_sum(), version_sum, "{}", hex);
+            assert_eq!(p.value(), Ok(value), "{}", hex);
+            assert_eq!(round_trip(&p), p, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn examples_encode_exactly() {
+        // these examples pad only to a whole hex digit and use minimal literal groups
+        for hex in ["D2FE28", "8A004A801A8002F478", "9C0141080250320F1802104A08"] {
+            assert_eq!(Packet::decode(hex).unwrap().to_hex().unwrap(), hex);
+        }
+    }
+
+    #[test]
+    fn both_length_types() {
+        let p = Packet::operator(
+            1,
+            PacketType::Sum,
+            LengthType::Bits,
+            vec![
+                Packet::literal(2, 7),
+                Packet::operator(
+                    3,
+                    PacketType::Product,
+                    LengthType::Count,
+                    vec![Packet::literal(4, 6), Packet::literal(5, 9)],
+                ),
+                Packet::operator(
+                    6,
+                    PacketType::Less,
+                    LengthType::Bits,
+                    vec![Packet::literal(7, 1), Packet::literal(0, u128::MAX)],
+                ),
+            ],
+        );
+        let decoded = round_trip(&p);
+        assert_eq!(decoded, p);
+        assert_eq!(decoded.value(), Ok(7 + 54 + 1));
+    }
+
+    #[test]
+    fn zero_literal() {
+        let p = Packet::literal(5, 0);
+        let bits = p.encode().unwrap();
+        assert_eq!(bits.len, 11);
+        assert_eq!(bits.to_hex(), "B00");
+        assert_eq!(round_trip(&p), p);
+    }
+
+    #[test]
+    fn operator_without_sub_packets() {
+        for length_type in [LengthType::Bits, LengthType::Count] {
+            let p = Packet::operator(2, PacketType::Max, length_type, vec![]);
+            let decoded = round_trip(&p);
+            assert_eq!(decoded, p);
+            assert_eq!(decoded.value(), Err(EvalError::NoOperands(PacketType::Max)));
+        }
+    }
+
+    #[test]
+    fn encode_errors() {
+        assert_eq!(
+            Packet::literal(8, 1).encode(),
+            Err(EncodeError::VersionTooLarge(8))
+        );
+        let many = vec![Packet::literal(0, 0); 1 << 11];
+        assert_eq!(
+            Packet::operator(0, PacketType::Sum, LengthType::Count, many).encode(),
+            Err(EncodeError::TooManyPackets(1 << 11))
+        );
+    }
+
+    /// `depth` sums nested one inside the other around a literal 1.
+    fn nested_hex(depth: usize) -> String {
+        let mut bits = BitWriter::new();
+        for _ in 0..depth {
+            // version 0, sum, one sub-packet by count
+            bits.write(7, 0b0000001);
+            bits.write(11, 1);
+        }
+        bits.write(11, 0b00010000001);
+        bits.to_hex()
+    }
+
+    #[test]
+    fn depth_limit() {
+        let p = Packet::decode(&nested_hex(MAX_DEPTH)).unwrap();
+        assert_eq!(p.value(), Ok(1));
+        assert_eq!(p.version_sum(), 0);
+        let closing = ")".repeat(MAX_DEPTH);
+        assert!(p.to_sexpr(false).ends_with(&format!(" 1{}", closing)));
+        assert_eq!(
+            Packet::decode(&nested_hex(MAX_DEPTH + 1)),
+            Err(DecodeError::TooDeep {
+                offset: 18 * MAX_DEPTH
+            })
+        );
+    }
 }
@@ -108,4 +108,13 @@ fn main() {
         }
     }
     println!("{}", grid.values().filter(|x| **x).count());
+
+    if std::env::args().any(|a| a == "--ocr") {
+        print_image(&grid, xbounds, ybounds);
+        let lit = grid.iter().filter(|(_, v)| **v).map(|(p, _)| *p).collect();
+        match aoc_ocr::recognise(&lit) {
+            Ok(text) => println!("{}", text),
+            Err(e) => eprintln!("{:?}", e),
+        }
+    }
 }
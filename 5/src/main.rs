@@ -51,6 +51,122 @@ impl SubLine {
     }
 }
 
+/// Number of lines covering each point, kept around so it can be queried and exported.
+struct VentMap {
+    counts: HashMap<(i64, i64), usize>,
+}
+
+impl VentMap {
+    fn new() -> VentMap {
+        VentMap {
+            counts: HashMap::new(),
+        }
+    }
+
+    fn add_point(&mut self, p: (i64, i64)) {
+        *self.counts.entry(p).or_insert(0) += 1;
+    }
+
+    fn at(&self, p: (i64, i64)) -> usize {
+        self.counts.get(&p).copied().unwrap_or(0)
+    }
+
+    /// Total overlap count over the inclusive rectangle between two corners.
+    fn in_rect(&self, a: (i64, i64), b: (i64, i64)) -> usize {
+        let (x0, x1) = (a.0.min(b.0), a.0.max(b.0));
+        let (y0, y1) = (a.1.min(b.1), a.1.max(b.1));
+        self.counts
+            .iter()
+            .filter(|(p, _)| p.0 >= x0 && p.0 <= x1 && p.1 >= y0 && p.1 <= y1)
+            .map(|(_, c)| c)
+            .sum()
+    }
+
+    fn points_with_overlap(&self, min: usize) -> usize {
+        self.counts.values().filter(|k| **k >= min).count()
+    }
+
+    fn max_overlap(&self) -> usize {
+        self.counts.values().copied().max().unwrap_or(0)
+    }
+
+    /// Number of points covered by exactly n lines, indexed by n (index 0 is always 0).
+    fn histogram(&self) -> Vec<usize> {
+        let mut hist = vec![0; self.max_overlap() + 1];
+        for c in self.counts.values() {
+            hist[*c] += 1;
+        }
+        hist
+    }
+
+    fn bounds(&self) -> ((i64, i64), (i64, i64)) {
+        if self.counts.is_empty() {
+            return ((0, 0), (0, 0));
+        }
+        let min_x = self.counts.keys().map(|p| p.0).min().unwrap();
+        let max_x = self.counts.keys().map(|p| p.0).max().unwrap();
+        let min_y = self.counts.keys().map(|p| p.1).min().unwrap();
+        let max_y = self.counts.keys().map(|p| p.1).max().unwrap();
+        ((min_x, min_y), (max_x, max_y))
+    }
+
+    /// Greyscale PGM covering the bounding box, brightness scaled to the highest overlap.
+    fn write_pgm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds();
+        let max = self.max_overlap().max(1);
+        writeln!(out, "P2")?;
+        writeln!(out, "{} {}", max_x - min_x + 1, max_y - min_y + 1)?;
+        writeln!(out, "255")?;
+        for y in min_y..=max_y {
+            let row = (min_x..=max_x)
+                .map(|x| (self.at((x, y)) * 255 / max).to_string())
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Colour PPM covering the bounding box: black for empty, blue for a single line,
+    /// shading from yellow to red as the overlap increases.
+    fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds();
+        let max = self.max_overlap().max(2);
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", max_x - min_x + 1, max_y - min_y + 1)?;
+        writeln!(out, "255")?;
+        for y in min_y..=max_y {
+            let row = (min_x..=max_x)
+                .map(|x| match self.at((x, y)) {
+                    0 => "0 0 0".to_owned(),
+                    1 => "0 0 255".to_owned(),
+                    c => {
+                        let green = 255 - (c - 2) * 255 / (max - 2).max(1);
+                        format!("255 {} 0", green)
+                    }
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Sparse CSV of every covered point, sorted by row then column.
+    fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut points = self.counts.iter().collect::<Vec<_>>();
+        points.sort_by_key(|(p, _)| (p.1, p.0));
+        writeln!(out, "x,y,count")?;
+        for (p, c) in points {
+            writeln!(out, "{},{},{}", p.0, p.1, c)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_point(s: &str) -> (i64, i64) {
+    let vals = s.split(',').map(parse_input).collect::<Vec<i64>>();
+    (vals[0], vals[1])
+}
+
 fn main() {
     let stdin = io::stdin();
     let values: Vec<SubLine> = stdin
@@ -62,16 +178,16 @@ fn main() {
             s
         })
         .collect();
-    let mut p1counter = HashMap::new();
-    let mut p2counter = HashMap::new();
+    let mut p1counter = VentMap::new();
+    let mut p2counter = VentMap::new();
     for p in values.iter() {
         let mut s = p.start;
         let grad = p.grad();
         loop {
             if grad.0 == 0 || grad.1 == 0 {
-                *p1counter.entry(s).or_insert(0) += 1;
+                p1counter.add_point(s);
             }
-            *p2counter.entry(s).or_insert(0) += 1;
+            p2counter.add_point(s);
             if s == p.end {
                 break;
             }
@@ -79,8 +195,47 @@ fn main() {
             s.1 += grad.1;
         }
     }
-    let ans_part_1 = p1counter.values().filter(|k| **k >= 2).count();
+    let ans_part_1 = p1counter.points_with_overlap(2);
     println!("{}", ans_part_1);
-    let ans_part_2 = p2counter.values().filter(|k| **k >= 2).count();
+    let ans_part_2 = p2counter.points_with_overlap(2);
     println!("{}", ans_part_2);
+
+    // optional queries / exports, run against the map including diagonals
+    // unless --straight is given
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let map = if args.iter().any(|a| a == "--straight") {
+        &p1counter
+    } else {
+        &p2counter
+    };
+    let mut args = args.iter().filter(|a| *a != "--straight");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => {
+                let p = parse_point(args.next().expect("--at needs x,y"));
+                println!("{:?}: {}", p, map.at(p));
+            }
+            "--rect" => {
+                let a = parse_point(args.next().expect("--rect needs x0,y0 x1,y1"));
+                let b = parse_point(args.next().expect("--rect needs x0,y0 x1,y1"));
+                println!("{:?}-{:?}: {}", a, b, map.in_rect(a, b));
+            }
+            "--histogram" => {
+                for (level, count) in map.histogram().iter().enumerate().skip(1) {
+                    println!("{}: {}", level, count);
+                }
+            }
+            "--pgm" | "--ppm" | "--csv" => {
+                let path = args.next().expect("export needs an output path");
+                let mut f = io::BufWriter::new(std::fs::File::create(path).unwrap());
+                match arg.as_str() {
+                    "--pgm" => map.write_pgm(&mut f),
+                    "--ppm" => map.write_ppm(&mut f),
+                    _ => map.write_csv(&mut f),
+                }
+                .unwrap();
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
 }
@@ -17,39 +17,364 @@ where
     input.as_ref().trim().parse().unwrap()
 }
 
-fn sub_move_p1(init_pos: &Vec<i64>, final_pos: i64) -> i64 {
-    init_pos.iter().map(|x| (x - final_pos).abs()).sum()
+/// Fuel needed to move a single crab a given (non-negative) distance.
+trait FuelCost {
+    fn cost(&self, distance: i64) -> i64;
+
+    /// Whether cost is convex and non-decreasing in distance, which makes the total
+    /// cost convex in the target position and lets the optimum be searched for.
+    fn is_convex(&self) -> bool {
+        true
+    }
+
+    /// `(a, b, d)` when cost is `(a * distance^2 + b * distance) / d`, which lets the cost of
+    /// a group of crabs be worked out from running sums instead of crab by crab.
+    fn polynomial(&self) -> Option<(i64, i64, i64)> {
+        None
+    }
+}
+
+struct Linear;
+
+impl FuelCost for Linear {
+    fn cost(&self, distance: i64) -> i64 {
+        distance
+    }
+
+    fn polynomial(&self) -> Option<(i64, i64, i64)> {
+        Some((0, 1, 1))
+    }
 }
 
-fn sub_move_p2(init_pos: &Vec<i64>, final_pos: i64) -> i64 {
+struct Triangular;
+
+impl FuelCost for Triangular {
+    fn cost(&self, distance: i64) -> i64 {
+        (distance * (distance + 1)) / 2
+    }
+
+    fn polynomial(&self) -> Option<(i64, i64, i64)> {
+        Some((1, 1, 2))
+    }
+}
+
+struct Quadratic;
+
+impl FuelCost for Quadratic {
+    fn cost(&self, distance: i64) -> i64 {
+        distance * distance
+    }
+
+    fn polynomial(&self) -> Option<(i64, i64, i64)> {
+        Some((1, 0, 1))
+    }
+}
+
+/// User supplied cost function; set `convex` to false to force an exhaustive search.
+struct Custom<F: Fn(i64) -> i64> {
+    f: F,
+    convex: bool,
+}
+
+impl<F: Fn(i64) -> i64> FuelCost for Custom<F> {
+    fn cost(&self, distance: i64) -> i64 {
+        (self.f)(distance)
+    }
+
+    fn is_convex(&self) -> bool {
+        self.convex
+    }
+}
+
+fn total_cost(init_pos: &[i64], final_pos: i64, cost: &dyn FuelCost) -> i64 {
     init_pos
         .iter()
-        .map(|x| {
-            let n = (x - final_pos).abs();
-            (n * (n + 1)) / 2
-        })
+        .map(|x| cost.cost((x - final_pos).abs()))
+        .sum()
+}
+
+fn sub_move_p1(init_pos: &[i64], final_pos: i64) -> i64 {
+    total_cost(init_pos, final_pos, &Linear)
+}
+
+fn sub_move_p2(init_pos: &[i64], final_pos: i64) -> i64 {
+    total_cost(init_pos, final_pos, &Triangular)
+}
+
+struct Alignment {
+    position: i64,
+    cost: i64,
+}
+
+/// Lowest position in lo..=hi minimising a convex function, found by binary searching
+/// for the point where the slope stops being negative.
+fn convex_minimum<F: Fn(i64) -> i64>(mut lo: i64, mut hi: i64, f: F) -> i64 {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if f(mid) <= f(mid + 1) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    lo
+}
+
+/// A crab at a horizontal position whose fuel use is scaled by weight.
+#[derive(Clone, Copy, Debug)]
+struct Crab {
+    pos: i64,
+    weight: i64,
+}
+
+impl Crab {
+    /// Parses either `pos` or `pos:weight`. Weights must be positive, as the searches rely on
+    /// the total cost being convex.
+    fn parse_input(s: &str) -> Result<Crab, String> {
+        let crab = match s.split_once(':') {
+            Some((pos, weight)) => Crab {
+                pos: parse_input(pos),
+                weight: parse_input(weight),
+            },
+            None => Crab {
+                pos: parse_input(s),
+                weight: 1,
+            },
+        };
+        if crab.weight <= 0 {
+            return Err(format!("crab '{}' needs a positive weight", s.trim()));
+        }
+        Ok(crab)
+    }
+}
+
+fn weighted_cost(crabs: &[Crab], final_pos: i64, cost: &dyn FuelCost) -> i64 {
+    crabs
+        .iter()
+        .map(|c| c.weight * cost.cost((c.pos - final_pos).abs()))
         .sum()
 }
 
+/// Best single target for a set of crabs, searched between the outermost crabs.
+fn best_position(crabs: &[Crab], cost: &dyn FuelCost) -> (i64, i64) {
+    let lo = crabs.iter().map(|c| c.pos).min().unwrap();
+    let hi = crabs.iter().map(|c| c.pos).max().unwrap();
+    let position = if cost.is_convex() {
+        convex_minimum(lo, hi, |p| weighted_cost(crabs, p, cost))
+    } else {
+        (lo..=hi)
+            .min_by_key(|p| weighted_cost(crabs, *p, cost))
+            .unwrap()
+    };
+    (position, weighted_cost(crabs, position, cost))
+}
+
+fn align_weighted(crabs: &[Crab], cost: &dyn FuelCost) -> Alignment {
+    let (position, total) = best_position(crabs, cost);
+    Alignment {
+        position,
+        cost: total,
+    }
+}
+
+/// (position, total cost) for every position between the outermost crabs.
+fn cost_curve(crabs: &[Crab], cost: &dyn FuelCost) -> Vec<(i64, i64)> {
+    let lo = crabs.iter().map(|c| c.pos).min().unwrap();
+    let hi = crabs.iter().map(|c| c.pos).max().unwrap();
+    (lo..=hi)
+        .map(|p| (p, weighted_cost(crabs, p, cost)))
+        .collect()
+}
+
+/// Best target and cost for any contiguous run of crabs sorted by position, using prefix
+/// sums of w, w*x and w*x^2 so a run's cost at one position takes a binary search.
+struct GroupCosts<'a> {
+    sorted: &'a [Crab],
+    poly: (i64, i64, i64),
+    w: Vec<i64>,
+    wx: Vec<i64>,
+    wxx: Vec<i64>,
+}
+
+impl<'a> GroupCosts<'a> {
+    fn new(sorted: &'a [Crab], poly: (i64, i64, i64)) -> GroupCosts<'a> {
+        let mut w = vec![0];
+        let mut wx = vec![0];
+        let mut wxx = vec![0];
+        for c in sorted.iter() {
+            w.push(w.last().unwrap() + c.weight);
+            wx.push(wx.last().unwrap() + c.weight * c.pos);
+            wxx.push(wxx.last().unwrap() + c.weight * c.pos * c.pos);
+        }
+        GroupCosts {
+            sorted,
+            poly,
+            w,
+            wx,
+            wxx,
+        }
+    }
+
+    /// Cost of moving sorted[l..i] to p.
+    fn cost_at(&self, l: usize, i: usize, p: i64) -> i64 {
+        let (a, b, d) = self.poly;
+        let m = l + self.sorted[l..i].partition_point(|c| c.pos < p);
+        let sum = |v: &[i64], from: usize, to: usize| v[to] - v[from];
+        let below = p * sum(&self.w, l, m) - sum(&self.wx, l, m);
+        let above = sum(&self.wx, m, i) - p * sum(&self.w, m, i);
+        let squares =
+            sum(&self.wxx, l, i) - 2 * p * sum(&self.wx, l, i) + p * p * sum(&self.w, l, i);
+        (a * squares + b * (below + above)) / d
+    }
+
+    fn best(&self, l: usize, i: usize) -> (i64, i64) {
+        let (a, b, _) = self.poly;
+        let mut lo = self.sorted[l].pos;
+        let mut hi = self.sorted[i - 1].pos;
+        if a > 0 {
+            // the real optimum is within b / 2a of the weighted mean
+            let mean = (self.wx[i] - self.wx[l]).div_euclid(self.w[i] - self.w[l]);
+            let slack = (b + 2 * a - 1) / (2 * a) + 1;
+            lo = lo.max(mean - slack);
+            hi = hi.min(mean + slack + 1);
+        }
+        let position = convex_minimum(lo, hi, |p| self.cost_at(l, i, p));
+        (position, self.cost_at(l, i, position))
+    }
+}
+
+struct MultiAlignment {
+    /// Chosen targets in increasing order.
+    positions: Vec<i64>,
+    /// Index into `positions` for each crab, in input order.
+    assignment: Vec<usize>,
+    cost: i64,
+}
+
+/// Aligns crabs onto up to k targets. With a convex cost each target takes a contiguous
+/// run of the crabs sorted by position, so this is a dynamic program over split points.
+/// Each of the O(n^2) runs is costed once; polynomial costs take O(log n log R) a run, other
+/// costs fall back to searching the run crab by crab.
+fn align_many(crabs: &[Crab], k: usize, cost: &dyn FuelCost) -> MultiAlignment {
+    let mut order = (0..crabs.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| crabs[i].pos);
+    let sorted = order.iter().map(|&i| crabs[i]).collect::<Vec<_>>();
+    let n = sorted.len();
+    // more targets than distinct positions can't help
+    let mut distinct = sorted.iter().map(|c| c.pos).collect::<Vec<_>>();
+    distinct.dedup();
+    let k = k.clamp(1, distinct.len());
+
+    let groups = cost.polynomial().map(|poly| GroupCosts::new(&sorted, poly));
+    // best target and cost for sorted[l..i]
+    let group_cost = |l: usize, i: usize| -> (i64, i64) {
+        match &groups {
+            Some(g) => g.best(l, i),
+            None => best_position(&sorted[l..i], cost),
+        }
+    };
+
+    // dp[j][i] is the cheapest way to send the first i crabs to j targets
+    let mut dp = vec![vec![i64::MAX; n + 1]; k + 1];
+    let mut split = vec![vec![0; n + 1]; k + 1];
+    dp[0][0] = 0;
+    for i in 1..=n {
+        for l in 0..i {
+            let g = group_cost(l, i).1;
+            for j in 1..=k {
+                if dp[j - 1][l] == i64::MAX {
+                    continue;
+                }
+                let c = dp[j - 1][l] + g;
+                if c < dp[j][i] {
+                    dp[j][i] = c;
+                    split[j][i] = l;
+                }
+            }
+        }
+    }
+
+    let mut positions = vec![];
+    let mut assignment = vec![0; n];
+    let mut i = n;
+    for j in (1..=k).rev() {
+        let l = split[j][i];
+        positions.push(group_cost(l, i).0);
+        for &crab in order[l..i].iter() {
+            assignment[crab] = j - 1;
+        }
+        i = l;
+    }
+    positions.reverse();
+    // neighbouring groups can still settle on the same target, so merge them
+    let mut merged: Vec<i64> = vec![];
+    let mut remap = vec![];
+    for p in positions {
+        if merged.last() != Some(&p) {
+            merged.push(p);
+        }
+        remap.push(merged.len() - 1);
+    }
+    let positions = merged;
+    for a in assignment.iter_mut() {
+        *a = remap[*a];
+    }
+    MultiAlignment {
+        positions,
+        assignment,
+        cost: dp[k][n],
+    }
+}
+
 fn main() {
     let stdin = io::stdin();
-    let values: Vec<Vec<i64>> = stdin
+    let values: Vec<Vec<Crab>> = stdin
         .lock()
         .lines()
-        .map(|input| input.unwrap().split(",").map(|x| parse_input(x)).collect())
+        .map(|input| {
+            input
+                .unwrap()
+                .split(',')
+                .map(Crab::parse_input)
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                })
+        })
         .collect();
 
-    let mut hpos = values[0].clone();
-    hpos.sort();
-    let median = hpos[hpos.len() / 2];
-    let ans = sub_move_p1(&hpos, median);
-    println!("{:?}", ans);
-
-    let count = hpos.len() as i64;
-    let total: i64 = hpos.iter().sum();
-    let mean = (total) / count;
-    println!(
-        "{:?}",
-        std::cmp::min(sub_move_p2(&hpos, mean), sub_move_p2(&hpos, mean + 1))
-    );
+    let crabs = values[0].clone();
+
+    let p1 = align_weighted(&crabs, &Linear);
+    println!("{:?}", p1.cost);
+    let p2 = align_weighted(&crabs, &Triangular);
+    println!("{:?}", p2.cost);
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|a| a == "--curve") {
+        let models: Vec<(&str, Box<dyn FuelCost>)> = vec![
+            ("linear", Box::new(Linear)),
+            ("triangular", Box::new(Triangular)),
+            ("quadratic", Box::new(Quadratic)),
+        ];
+        for (name, cost) in models.iter() {
+            let a = align_weighted(&crabs, cost.as_ref());
+            println!("{}: position {} cost {}", name, a.position, a.cost);
+            for (p, c) in cost_curve(&crabs, cost.as_ref()) {
+                println!("  {} {}", p, c);
+            }
+        }
+    }
+    if let Some(i) = args.iter().position(|a| a == "--targets") {
+        let k: usize = parse_input(args.get(i + 1).expect("--targets needs a count"));
+        for (name, cost) in [
+            ("linear", &Linear as &dyn FuelCost),
+            ("triangular", &Triangular),
+        ] {
+            let m = align_many(&crabs, k, cost);
+            println!("{}: positions {:?} cost {}", name, m.positions, m.cost);
+            println!("  assignment {:?}", m.assignment);
+        }
+    }
 }
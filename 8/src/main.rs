@@ -17,64 +17,257 @@ where
     input.as_ref().trim().parse().unwrap()
 }
 
-fn create_mapping(s: &str) -> HashMap<char, u8> {
-    let mut hm = HashMap::new();
-    let mut counter = HashMap::new();
-    let diffs: Vec<String> = s.split(' ').map(|x| x.to_owned()).collect();
-    for x in s.chars() {
-        if x == ' ' {
-            continue;
+/// Lit segments for each digit, bit n set for segment n:
+/// 0 top, 1 top left, 2 top right, 3 middle, 4 bottom left, 5 bottom right, 6 bottom.
+const DIGITS: [u32; 10] = [
+    0b1110111, 0b0100100, 0b1011101, 0b1101101, 0b0101110, 0b1101011, 0b1111011, 0b0100101,
+    0b1111111, 0b1101111,
+];
+
+/// A display's glyph set: how many segments it has and which of them each symbol lights.
+/// Segment n is driven by the nth wire letter, so a 7-segment display uses wires a-g.
+struct Encoding {
+    segments: usize,
+    glyphs: Vec<(String, u32)>,
+    /// Wire letter for each segment, a onwards.
+    wires: Vec<char>,
+}
+
+impl Encoding {
+    fn new(segments: usize, glyphs: Vec<(String, u32)>) -> Encoding {
+        let wires = (0..segments as u8).map(|i| (b'a' + i) as char).collect();
+        Encoding {
+            segments,
+            glyphs,
+            wires,
+        }
+    }
+
+    fn standard() -> Encoding {
+        Encoding::new(
+            7,
+            DIGITS
+                .iter()
+                .enumerate()
+                .map(|(d, mask)| (d.to_string(), *mask))
+                .collect(),
+        )
+    }
+
+    /// Reads an encoding file. The first non-comment line is `segments N`, followed by one
+    /// `symbol segments` line per glyph where segments are letters, e.g. `7 acf` for a
+    /// standard 7. Lines starting with `#` are ignored.
+    fn parse_input(s: &str) -> Result<Encoding, String> {
+        let mut lines = s
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'));
+        let header = lines.next().ok_or("empty encoding")?;
+        let segments = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["segments", n] => n.parse::<usize>().map_err(|e| e.to_string())?,
+            _ => return Err(format!("expected 'segments N', got '{}'", header)),
+        };
+        if segments == 0 || segments > 26 {
+            return Err(format!("unsupported segment count {}", segments));
         }
-        *counter.entry(x).or_insert(0) += 1;
-    }
-    let one = diffs.iter().find(|x| x.len() == 2).unwrap().to_owned();
-    let four = diffs.iter().find(|x| x.len() == 4).unwrap().to_owned();
-    for (k, v) in counter.iter() {
-        if *v == 4 {
-            hm.insert(*k, 4);
-        } else if *v == 6 {
-            hm.insert(*k, 1);
-        } else if *v == 8 {
-            if one.contains(*k) {
-                hm.insert(*k, 2);
-            } else {
-                hm.insert(*k, 0);
+        let mut glyphs: Vec<(String, u32)> = vec![];
+        for l in lines {
+            let (symbol, lit) = l
+                .split_once(char::is_whitespace)
+                .ok_or(format!("glyph '{}' has no segments", l))?;
+            let mut mask = 0;
+            for c in lit.trim().chars() {
+                let seg = (c as u32).wrapping_sub('a' as u32);
+                if seg as usize >= segments {
+                    return Err(format!("glyph '{}' uses unknown segment '{}'", symbol, c));
+                }
+                mask |= 1 << seg;
             }
-        } else if *v == 9 {
-            hm.insert(*k, 5);
-        } else if *v == 7 {
-            if four.contains(*k) {
-                hm.insert(*k, 3);
-            } else {
-                hm.insert(*k, 6);
+            if let Some((other, _)) = glyphs.iter().find(|(_, m)| *m == mask) {
+                return Err(format!("glyphs '{}' and '{}' are identical", other, symbol));
             }
+            glyphs.push((symbol.to_owned(), mask));
+        }
+        Ok(Encoding::new(segments, glyphs))
+    }
+
+    /// Whether only one glyph lights this many segments, so a pattern's length alone
+    /// identifies it.
+    fn is_unique_length(&self, len: usize) -> bool {
+        self.glyphs
+            .iter()
+            .filter(|(_, m)| m.count_ones() as usize == len)
+            .count()
+            == 1
+    }
+
+    /// Whether every symbol is a decimal digit, so decoded outputs are numbers.
+    fn is_decimal(&self) -> bool {
+        self.glyphs
+            .iter()
+            .all(|(s, _)| s.len() == 1 && s.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    fn symbol(&self, mask: u32) -> Option<&str> {
+        self.glyphs
+            .iter()
+            .find(|(_, m)| *m == mask)
+            .map(|(s, _)| s.as_str())
+    }
+
+    /// Can some glyph with this many segments have all of `mask` lit?
+    fn could_match(&self, len: usize, mask: u32) -> bool {
+        self.glyphs
+            .iter()
+            .any(|(_, m)| m.count_ones() as usize == len && m & mask == mask)
+    }
+}
+
+#[derive(Debug)]
+enum DecodeError {
+    /// A pattern uses a wire the display doesn't have or repeats one.
+    InvalidPattern(String),
+    /// No wiring maps every observed pattern onto a glyph.
+    NoWiring,
+    /// More than one wiring fits and they disagree on the output.
+    Ambiguous {
+        wirings: Vec<HashMap<char, u8>>,
+        values: Vec<String>,
+    },
+}
+
+fn pattern_mask(pattern: &str, mapping: &HashMap<char, u8>) -> u32 {
+    pattern
+        .chars()
+        .filter_map(|c| mapping.get(&c))
+        .fold(0, |acc, seg| acc | 1 << seg)
+}
+
+fn validate_pattern(pattern: &str, encoding: &Encoding) -> Result<(), DecodeError> {
+    let wires = &encoding.wires;
+    let mut seen = HashSet::new();
+    for c in pattern.chars() {
+        if !wires.contains(&c) || !seen.insert(c) {
+            return Err(DecodeError::InvalidPattern(pattern.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Every wiring (wire -> segment) under which all of the given patterns show a glyph.
+/// Patterns can be any subset of the glyphs, so partial observations give several answers.
+fn find_wirings(
+    patterns: &[&str],
+    encoding: &Encoding,
+) -> Result<Vec<HashMap<char, u8>>, DecodeError> {
+    for p in patterns.iter() {
+        validate_pattern(p, encoding)?;
+    }
+    // a wire lit in a pattern of length n can only drive a segment used by an n-segment glyph
+    let all = (1u32 << encoding.segments) - 1;
+    let mut candidates: HashMap<char, u32> = encoding.wires.iter().map(|w| (*w, all)).collect();
+    for p in patterns.iter() {
+        let allowed = encoding
+            .glyphs
+            .iter()
+            .filter(|(_, m)| m.count_ones() as usize == p.len())
+            .fold(0, |acc, (_, m)| acc | m);
+        for c in p.chars() {
+            *candidates.get_mut(&c).unwrap() &= allowed;
+        }
+    }
+
+    let mut found = vec![];
+    let mut mapping = HashMap::new();
+    search_wirings(
+        0,
+        0,
+        encoding,
+        &candidates,
+        patterns,
+        &mut mapping,
+        &mut found,
+    );
+    Ok(found)
+}
+
+fn search_wirings(
+    wire: usize,
+    used: u32,
+    encoding: &Encoding,
+    candidates: &HashMap<char, u32>,
+    patterns: &[&str],
+    mapping: &mut HashMap<char, u8>,
+    found: &mut Vec<HashMap<char, u8>>,
+) {
+    // prune as soon as a pattern's wired segments can't be part of any glyph its length
+    if !patterns
+        .iter()
+        .all(|p| encoding.could_match(p.len(), pattern_mask(p, mapping)))
+    {
+        return;
+    }
+    let wires = &encoding.wires;
+    if wire == wires.len() {
+        found.push(mapping.clone());
+        return;
+    }
+    let c = wires[wire];
+    for segment in 0..encoding.segments as u8 {
+        if used & (1 << segment) != 0 || candidates[&c] & (1 << segment) == 0 {
+            continue;
         }
+        mapping.insert(c, segment);
+        search_wirings(
+            wire + 1,
+            used | (1 << segment),
+            encoding,
+            candidates,
+            patterns,
+            mapping,
+            found,
+        );
     }
-    hm
+    mapping.remove(&c);
 }
 
-fn decode(s: &str, mapping: &HashMap<char, u8>) -> u32 {
+fn decode(
+    s: &str,
+    mapping: &HashMap<char, u8>,
+    encoding: &Encoding,
+) -> Result<String, DecodeError> {
     let mut out = String::new();
     for x in s.split_whitespace() {
-        let mut value = 0;
-        for c in x.chars() {
-            value |= 1 << mapping[&c];
+        validate_pattern(x, encoding)?;
+        match encoding.symbol(pattern_mask(x, mapping)) {
+            Some(symbol) => out += symbol,
+            None => return Err(DecodeError::NoWiring),
         }
-        out += match value {
-            0b1110111 => "0",
-            0b0100100 => "1",
-            0b1011101 => "2",
-            0b1101101 => "3",
-            0b0101110 => "4",
-            0b1101011 => "5",
-            0b1111011 => "6",
-            0b0100101 => "7",
-            0b1111111 => "8",
-            0b1101111 => "9",
-            _ => panic!("unknown display value {}", value),
-        };
     }
-    parse_input(out)
+    Ok(out)
+}
+
+/// Works out the wiring from both halves of an entry and decodes the output.
+fn solve(signals: &str, output: &str, encoding: &Encoding) -> Result<String, DecodeError> {
+    let patterns = signals
+        .split_whitespace()
+        .chain(output.split_whitespace())
+        .collect::<Vec<_>>();
+    let wirings = find_wirings(&patterns, encoding)?;
+    if wirings.is_empty() {
+        return Err(DecodeError::NoWiring);
+    }
+    let mut values = wirings
+        .iter()
+        .map(|w| decode(output, w, encoding))
+        .collect::<Result<Vec<_>, _>>()?;
+    values.sort_unstable();
+    values.dedup();
+    if values.len() == 1 {
+        Ok(values.remove(0))
+    } else {
+        Err(DecodeError::Ambiguous { wirings, values })
+    }
 }
 
 fn main() {
@@ -88,20 +281,39 @@ fn main() {
             (s[0].to_owned(), s[1].to_owned())
         })
         .collect();
+    let args = std::env::args().collect::<Vec<_>>();
+    let encoding = match args.iter().position(|a| a == "--encoding") {
+        Some(i) => {
+            let path = args.get(i + 1).expect("--encoding needs a file");
+            Encoding::parse_input(&std::fs::read_to_string(path).unwrap()).unwrap()
+        }
+        None => Encoding::standard(),
+    };
+
     let mut counter = 0;
     for x in values.iter() {
         counter +=
             x.1.split(' ')
-                .filter(|x| x.len() < 5 || x.len() > 6)
+                .filter(|x| encoding.is_unique_length(x.len()))
                 .count();
     }
     println!("{}", counter);
 
+    let decimal = encoding.is_decimal();
     let mut total = 0;
-    for x in values.iter() {
-        let mapping = create_mapping(&x.0);
-        let value = decode(&x.1, &mapping);
-        total += value;
+    for (line, x) in values.iter().enumerate() {
+        match solve(&x.0, &x.1, &encoding) {
+            // non-numeric glyph sets are listed rather than summed
+            Ok(value) if decimal => total += parse_input::<_, u64>(value),
+            Ok(value) => println!("{}", value),
+            Err(DecodeError::Ambiguous { wirings, values }) => eprintln!(
+                "line {}: {} wirings fit, possible outputs {:?}",
+                line + 1,
+                wirings.len(),
+                values
+            ),
+            Err(e) => eprintln!("line {}: {:?}", line + 1, e),
+        }
     }
     println!("{}", total);
 }
@@ -17,56 +17,343 @@ where
     input.as_ref().trim().parse().unwrap()
 }
 
-const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (-1, 0), (0, -1)];
-
-fn calc_basin(grid: &HashMap<(i32, i32), u32>, p: (i32, i32)) -> usize {
-    let mut basin = HashSet::new();
-    basin.insert(p);
-    let mut current = VecDeque::new();
-    current.push_back(p);
-
-    while current.is_empty() == false {
-        let p = current.pop_front().unwrap();
-        for d in DIRECTIONS.iter() {
-            let nd = (p.0 + d.0, p.1 + d.1);
-            let v = *grid.get(&nd).unwrap_or(&9);
-            if v != 9 && basin.insert(nd) {
-                current.push_back(nd);
+type Point = (i32, i32, i32);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Connectivity {
+    /// Neighbours share a face: 4 in a single layer, 6 across layers.
+    Faces,
+    /// Neighbours share a face, edge or corner: 8 in a single layer, 26 across layers.
+    All,
+}
+
+struct Settings {
+    /// Cells at or above this height are walls between basins.
+    wall: u32,
+    connectivity: Connectivity,
+}
+
+impl Settings {
+    fn new() -> Settings {
+        Settings {
+            wall: 9,
+            connectivity: Connectivity::Faces,
+        }
+    }
+}
+
+/// Heights on an x/y grid, optionally stacked into several z layers.
+struct Heightmap {
+    width: i32,
+    height: i32,
+    layers: i32,
+    cells: Vec<u32>,
+}
+
+impl Heightmap {
+    /// Rows of single digits, or of whitespace/comma separated numbers for heights above 9.
+    /// Blank lines separate z layers.
+    fn parse_input(lines: &[String]) -> Heightmap {
+        let mut rows: Vec<Vec<u32>> = vec![];
+        let mut layers = 0;
+        let mut in_layer = false;
+        for l in lines.iter().map(|l| l.trim()) {
+            if l.is_empty() {
+                in_layer = false;
+                continue;
+            }
+            if !in_layer {
+                layers += 1;
+                in_layer = true;
+            }
+            let row = if l.contains(|c: char| c == ',' || c.is_whitespace()) {
+                l.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|x| !x.is_empty())
+                    .map(parse_input)
+                    .collect()
+            } else {
+                l.chars().map(|c| c.to_digit(10).unwrap()).collect()
+            };
+            rows.push(row);
+        }
+        let width = rows.first().map_or(0, |r| r.len()) as i32;
+        assert!(
+            rows.iter().all(|r| r.len() as i32 == width),
+            "rows have different lengths"
+        );
+        assert!(
+            layers == 0 || rows.len().is_multiple_of(layers),
+            "layers have different heights"
+        );
+        let height = rows.len().checked_div(layers).unwrap_or(0) as i32;
+        Heightmap {
+            width,
+            height,
+            layers: layers as i32,
+            cells: rows.into_iter().flatten().collect(),
+        }
+    }
+
+    fn index(&self, p: Point) -> Option<usize> {
+        if p.0 < 0
+            || p.1 < 0
+            || p.2 < 0
+            || p.0 >= self.width
+            || p.1 >= self.height
+            || p.2 >= self.layers
+        {
+            return None;
+        }
+        Some(((p.2 * self.height + p.1) * self.width + p.0) as usize)
+    }
+
+    fn get(&self, p: Point) -> Option<u32> {
+        self.index(p).map(|i| self.cells[i])
+    }
+
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.layers).flat_map(move |z| {
+            (0..self.height).flat_map(move |y| (0..self.width).map(move |x| (x, y, z)))
+        })
+    }
+
+    /// Neighbour offsets, only reaching into other layers when there are any.
+    fn directions(&self, connectivity: Connectivity) -> Vec<Point> {
+        let dz = if self.layers > 1 { -1..=1 } else { 0..=0 };
+        let mut dirs = vec![];
+        for z in dz {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let d: Point = (x, y, z);
+                    let steps = d.0.abs() + d.1.abs() + d.2.abs();
+                    if steps == 1 || (steps > 1 && connectivity == Connectivity::All) {
+                        dirs.push(d);
+                    }
+                }
             }
         }
+        dirs
     }
 
-    basin.len()
+    /// Cells strictly lower than every neighbour, treating the edges as higher.
+    fn low_points(&self, settings: &Settings) -> Vec<Point> {
+        let dirs = self.directions(settings.connectivity);
+        self.points()
+            .filter(|p| {
+                let v = self.get(*p).unwrap();
+                v < settings.wall
+                    && dirs.iter().all(|d| {
+                        self.get((p.0 + d.0, p.1 + d.1, p.2 + d.2))
+                            .is_none_or(|n| n > v)
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Disjoint set forest over cell indices, with union by size and path halving.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+#[derive(Debug)]
+struct Basin {
+    size: usize,
+    low_points: Vec<Point>,
+    /// Height of the lowest cell in the basin.
+    depth: u32,
+    bounds: (Point, Point),
+}
+
+struct BasinMap {
+    width: i32,
+    height: i32,
+    layers: i32,
+    /// Basin index for every cell, None for walls.
+    labels: Vec<Option<usize>>,
+    basins: Vec<Basin>,
+}
+
+impl BasinMap {
+    fn label(&self, p: Point) -> Option<usize> {
+        if p.0 < 0
+            || p.1 < 0
+            || p.2 < 0
+            || p.0 >= self.width
+            || p.1 >= self.height
+            || p.2 >= self.layers
+        {
+            return None;
+        }
+        self.labels[((p.2 * self.height + p.1) * self.width + p.0) as usize]
+    }
+
+    /// Colour PPM with each basin in its own colour and walls in black. Layers are
+    /// stacked top to bottom with a grey separator row between them.
+    fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(
+            out,
+            "{} {}",
+            self.width,
+            self.height * self.layers + (self.layers - 1).max(0)
+        )?;
+        writeln!(out, "255")?;
+        for z in 0..self.layers {
+            if z > 0 {
+                let sep = vec!["128 128 128"; self.width as usize];
+                writeln!(out, "{}", sep.join(" "))?;
+            }
+            for y in 0..self.height {
+                let row = (0..self.width)
+                    .map(|x| match self.label((x, y, z)) {
+                        None => "0 0 0".to_owned(),
+                        Some(l) => {
+                            // spread consecutive labels around the colour space
+                            let h = (l as u32 + 1).wrapping_mul(2654435761);
+                            format!(
+                                "{} {} {}",
+                                64 + (h >> 8) % 192,
+                                64 + (h >> 16) % 192,
+                                64 + (h >> 24) % 192
+                            )
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", row.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Labels every non-wall cell with its basin (a connected component) in a single pass.
+fn find_basins(map: &Heightmap, settings: &Settings, low_points: &[Point]) -> BasinMap {
+    let open = |p: Point| map.get(p).is_some_and(|v| v < settings.wall);
+    // every link is seen from both ends, so only the forward half is needed
+    let dirs = map
+        .directions(settings.connectivity)
+        .into_iter()
+        .filter(|d| (d.2, d.1, d.0) > (0, 0, 0))
+        .collect::<Vec<_>>();
+
+    let mut uf = UnionFind::new(map.cells.len());
+    for p in map.points().filter(|p| open(*p)) {
+        for d in dirs.iter() {
+            let nd = (p.0 + d.0, p.1 + d.1, p.2 + d.2);
+            if open(nd) {
+                uf.union(map.index(p).unwrap(), map.index(nd).unwrap());
+            }
+        }
+    }
+
+    let mut labels = vec![None; map.cells.len()];
+    let mut roots = HashMap::new();
+    let mut basins: Vec<Basin> = vec![];
+    for p in map.points().filter(|p| open(*p)) {
+        let i = map.index(p).unwrap();
+        let root = uf.find(i);
+        let label = *roots.entry(root).or_insert_with(|| {
+            basins.push(Basin {
+                size: 0,
+                low_points: vec![],
+                depth: u32::MAX,
+                bounds: (p, p),
+            });
+            basins.len() - 1
+        });
+        labels[i] = Some(label);
+        let b = &mut basins[label];
+        b.size += 1;
+        b.depth = b.depth.min(map.cells[i]);
+        let (lo, hi) = b.bounds;
+        b.bounds = (
+            (lo.0.min(p.0), lo.1.min(p.1), lo.2.min(p.2)),
+            (hi.0.max(p.0), hi.1.max(p.1), hi.2.max(p.2)),
+        );
+    }
+    for p in low_points.iter() {
+        if let Some(l) = labels[map.index(*p).unwrap()] {
+            basins[l].low_points.push(*p);
+        }
+    }
+    BasinMap {
+        width: map.width,
+        height: map.height,
+        layers: map.layers,
+        labels,
+        basins,
+    }
 }
 
 fn main() {
     let stdin = io::stdin();
     let values: Vec<String> = stdin.lock().lines().map(|input| input.unwrap()).collect();
-    let mut grid = HashMap::new();
-    for (y, s) in values.iter().enumerate() {
-        for (x, v) in s.chars().enumerate() {
-            let p = (x as i32, y as i32);
-            let val = v.to_digit(10).unwrap();
-            grid.insert(p, val);
-        }
+    let map = Heightmap::parse_input(&values);
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut settings = Settings::new();
+    if let Some(i) = args.iter().position(|a| a == "--wall") {
+        settings.wall = parse_input(args.get(i + 1).expect("--wall needs a height"));
     }
-    let low_points: Vec<_> = grid
-        .iter()
-        .filter(|(p, v)| {
-            for d in DIRECTIONS.iter() {
-                let nd = (p.0 + d.0, p.1 + d.1);
-                if grid.get(&nd).unwrap_or(&10) <= *v {
-                    return false;
-                }
-            }
-            true
-        })
-        .collect();
-    let p1ans: u32 = low_points.iter().map(|x| x.1 + 1).sum();
+    if args.iter().any(|a| a == "--diagonal") {
+        settings.connectivity = Connectivity::All;
+    }
+
+    let low_points = map.low_points(&settings);
+    let p1ans: u32 = low_points.iter().map(|p| map.get(*p).unwrap() + 1).sum();
     println!("{:?}", p1ans);
 
-    let mut basins: Vec<_> = low_points.iter().map(|x| calc_basin(&grid, *x.0)).collect();
-    basins.sort();
-    let p2ans: usize = basins[(basins.len() - 3)..].iter().product();
+    let basin_map = find_basins(&map, &settings, &low_points);
+    for b in basin_map.basins.iter().filter(|b| b.low_points.len() != 1) {
+        eprintln!(
+            "basin at {:?} has {} low points",
+            b.bounds.0,
+            b.low_points.len()
+        );
+    }
+    let mut basins: Vec<_> = basin_map.basins.iter().map(|b| b.size).collect();
+    basins.sort_unstable();
+    // walls and diagonal joins can leave fewer than three basins; use what there is
+    if basins.len() < 3 {
+        eprintln!("fewer than 3 basins, multiplying all {}", basins.len());
+    }
+    let p2ans: usize = basins[basins.len().saturating_sub(3)..].iter().product();
     println!("{:?}", p2ans);
+
+    if let Some(i) = args.iter().position(|a| a == "--ppm") {
+        let path = args.get(i + 1).expect("--ppm needs an output path");
+        let mut f = io::BufWriter::new(std::fs::File::create(path).unwrap());
+        basin_map.write_ppm(&mut f).unwrap();
+    }
 }
@@ -0,0 +1,91 @@
+//! Unsigned integers of any size, for puzzle answers that outgrow u64.
+
+/// Unsigned integer stored as little-endian base 10^9 limbs, with no high zero limbs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint(Vec<u32>);
+
+impl BigUint {
+    const BASE: u64 = 1_000_000_000;
+
+    pub fn zero() -> BigUint {
+        BigUint(vec![])
+    }
+
+    pub fn one() -> BigUint {
+        BigUint(vec![1])
+    }
+
+    pub fn from_u64(mut v: u64) -> BigUint {
+        let mut limbs = vec![];
+        while v > 0 {
+            limbs.push((v % Self::BASE) as u32);
+            v /= Self::BASE;
+        }
+        BigUint(limbs)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn trim(mut self) -> BigUint {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        self
+    }
+
+    /// Adds `other` in place, so running totals don't reallocate on every step.
+    pub fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0;
+        for i in 0..self.0.len().max(other.0.len()) {
+            if i == self.0.len() {
+                self.0.push(0);
+            }
+            let sum = self.0[i] as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            self.0[i] = (sum % Self::BASE) as u32;
+            carry = sum / Self::BASE;
+        }
+        if carry > 0 {
+            self.0.push(carry as u32);
+        }
+    }
+
+    pub fn add(&self, other: &BigUint) -> BigUint {
+        let mut out = self.clone();
+        out.add_assign(other);
+        out
+    }
+
+    pub fn mul(&self, other: &BigUint) -> BigUint {
+        if self.is_zero() || other.is_zero() {
+            return BigUint::zero();
+        }
+        let mut out = vec![0u64; self.0.len() + other.0.len()];
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0;
+            for (j, b) in other.0.iter().enumerate() {
+                let cur = out[i + j] + *a as u64 * *b as u64 + carry;
+                out[i + j] = cur % Self::BASE;
+                carry = cur / Self::BASE;
+            }
+            out[i + other.0.len()] += carry;
+        }
+        BigUint(out.into_iter().map(|x| x as u32).collect()).trim()
+    }
+}
+
+impl std::fmt::Display for BigUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0.split_last() {
+            None => write!(f, "0"),
+            Some((top, rest)) => {
+                write!(f, "{}", top)?;
+                for limb in rest.iter().rev() {
+                    write!(f, "{:09}", limb)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
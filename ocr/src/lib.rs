@@ -0,0 +1,159 @@
+//! Reads the block letters that several puzzles draw out of points, in either the usual
+//! 4x6 font or the taller 6x10 one.
+
+use std::collections::*;
+
+const SMALL_HEIGHT: usize = 6;
+const LARGE_HEIGHT: usize = 10;
+
+const SMALL_FONT: [(char, [&str; SMALL_HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[rustfmt::skip]
+const LARGE_FONT: [(char, [&str; LARGE_HEIGHT]); 15] = [
+    ('A', ["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"]),
+    ('B', ["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."]),
+    ('C', [".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."]),
+    ('E', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('F', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('G', [".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+    ('H', ["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"]),
+    ('J', ["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+    ('K', ["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+    ('L', ["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('N', ["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+    ('P', ["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('R', ["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+    ('X', ["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+    ('Z', ["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"]),
+];
+
+/// A glyph that didn't match any letter in the font.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownGlyph {
+    /// Position of the glyph in the text, counting from 0.
+    pub index: usize,
+    /// The glyph as rows of `#` and `.`.
+    pub bitmap: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OcrError {
+    /// The lit points aren't the height of either font.
+    UnsupportedHeight(usize),
+    /// Some glyphs weren't recognised; `text` has `?` in their place.
+    UnknownGlyphs {
+        text: String,
+        glyphs: Vec<UnknownGlyph>,
+    },
+}
+
+/// Bitmap rows with empty columns trimmed from both sides.
+fn trim(rows: &[String]) -> Vec<String> {
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let lit = |x: usize| rows.iter().any(|r| r.as_bytes().get(x) == Some(&b'#'));
+    let first = (0..width).find(|&x| lit(x)).unwrap_or(0);
+    let last = (0..width).rev().find(|&x| lit(x)).map_or(0, |x| x + 1);
+    rows.iter()
+        .map(|r| {
+            (first..last.max(first))
+                .map(|x| {
+                    if r.as_bytes().get(x) == Some(&b'#') {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn lookup(glyph: &[String]) -> Option<char> {
+    let matches =
+        |rows: &[&str]| trim(&rows.iter().map(|r| r.to_string()).collect::<Vec<_>>()) == glyph;
+    if glyph.len() == SMALL_HEIGHT {
+        SMALL_FONT
+            .iter()
+            .find(|(_, rows)| matches(rows))
+            .map(|(c, _)| *c)
+    } else {
+        LARGE_FONT
+            .iter()
+            .find(|(_, rows)| matches(rows))
+            .map(|(c, _)| *c)
+    }
+}
+
+/// Reads the text drawn by a set of lit points. Glyphs are split on empty columns, so the
+/// spacing between letters doesn't matter.
+pub fn recognise(points: &HashSet<(i32, i32)>) -> Result<String, OcrError> {
+    if points.is_empty() {
+        return Ok(String::new());
+    }
+    let min_x = points.iter().map(|p| p.0).min().unwrap();
+    let max_x = points.iter().map(|p| p.0).max().unwrap();
+    let min_y = points.iter().map(|p| p.1).min().unwrap();
+    let max_y = points.iter().map(|p| p.1).max().unwrap();
+    let height = (max_y - min_y + 1) as usize;
+    if height != SMALL_HEIGHT && height != LARGE_HEIGHT {
+        return Err(OcrError::UnsupportedHeight(height));
+    }
+
+    let column_lit = |x: i32| (min_y..=max_y).any(|y| points.contains(&(x, y)));
+    let mut text = String::new();
+    let mut unknown = vec![];
+    let mut x = min_x;
+    while x <= max_x {
+        if !column_lit(x) {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        while x <= max_x && column_lit(x) {
+            x += 1;
+        }
+        let glyph = (min_y..=max_y)
+            .map(|y| {
+                (start..x)
+                    .map(|gx| if points.contains(&(gx, y)) { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        match lookup(&glyph) {
+            Some(c) => text.push(c),
+            None => {
+                unknown.push(UnknownGlyph {
+                    index: text.chars().count(),
+                    bitmap: glyph,
+                });
+                text.push('?');
+            }
+        }
+    }
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(OcrError::UnknownGlyphs {
+            text,
+            glyphs: unknown,
+        })
+    }
+}